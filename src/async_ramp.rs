@@ -0,0 +1,316 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
+
+use crate::curve::Curve;
+use crate::ramp::{longest_duration, CancelToken, InterpolationOptions, Ramp, Transition};
+use crate::{write_brightness, BoxedDevice, Result};
+
+/// A ramp running as a task of a tokio runtime, which can be awaited
+/// for the result of the ramp once it has finished.
+///
+/// Dropping the handle does not stop the ramp, see [`RampHandle::cancel`].
+#[derive(Debug)]
+pub struct RampHandle {
+    cancel: CancelToken,
+    task: JoinHandle<Result<()>>,
+}
+
+impl RampHandle {
+    /// Stop the ramp where it is, after which awaiting the handle returns `Ok`.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+        self.task.abort();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl Future for RampHandle {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.task)
+            .poll(cx)
+            .map(|joined| match joined {
+                Ok(result) => result,
+                Err(e) if e.is_cancelled() => Ok(()),
+                Err(e) => std::panic::resume_unwind(e.into_panic()),
+            })
+    }
+}
+
+/// Start a ramp of `device` to `target` over `duration` on the current
+/// runtime, as [`ramp_brightness`](crate::ramp::ramp_brightness) does
+/// on the current thread. With no duration, `target` is written at once.
+///
+/// The ramp also stops when `cancel` is cancelled, which is checked before
+/// each step. The device is only used on the blocking thread pool, because
+/// some devices, such as DDC/CI displays, take a while to respond.
+///
+/// # Panics
+///
+/// When called outside of a tokio runtime.
+pub fn spawn_ramp(
+    device: BoxedDevice,
+    target: u32,
+    duration: Duration,
+    interpolate: &InterpolationOptions,
+    curve: &Curve,
+    cancel: CancelToken,
+) -> RampHandle {
+    let (interpolate, curve) = (interpolate.clone(), curve.clone());
+    let task = tokio::spawn(run_ramp(
+        device,
+        target,
+        duration,
+        interpolate,
+        curve,
+        cancel.clone(),
+    ));
+    RampHandle { cancel, task }
+}
+
+/// Apply every transition concurrently on the current runtime, scaling the
+/// duration by the largest relative change so that all devices finish at
+/// the same time, as [`set_brightness_all`](crate::ramp::set_brightness_all)
+/// does with threads.
+///
+/// Unlike that, ramps of the same devices are neither tracked nor locked,
+/// which is left to the caller with `cancel`. If a ramp fails, `cancel` is
+/// cancelled to stop the others, and the first error is returned once they have.
+pub async fn set_brightness_all(
+    transitions: Vec<Transition>,
+    interpolate: &InterpolationOptions,
+    cancel: &CancelToken,
+) -> Result<()> {
+    let duration = longest_duration(&transitions, interpolate);
+    let mut ramps = JoinSet::new();
+    for t in transitions {
+        ramps.spawn(run_ramp(
+            t.device,
+            t.target,
+            duration,
+            interpolate.clone(),
+            t.curve,
+            cancel.clone(),
+        ));
+    }
+    // in the order they finish, so that a failure stops the others at once
+    let mut result = Ok(());
+    while let Some(joined) = ramps.join_next().await {
+        let done = joined.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()));
+        if result.is_ok() && done.is_err() {
+            cancel.cancel();
+            result = done;
+        }
+    }
+    result
+}
+
+async fn run_ramp(
+    device: BoxedDevice,
+    target: u32,
+    duration: Duration,
+    interpolate: InterpolationOptions,
+    curve: Curve,
+    cancel: CancelToken,
+) -> Result<()> {
+    let now = Instant::now().into_std();
+    let (mut device, ramp) = blocking(move || {
+        let ramp = if duration.is_zero() {
+            write_brightness(&*device, target).map(|()| None)
+        } else {
+            Ramp::start(&*device, target, duration, &interpolate, &curve, now)
+        };
+        (device, ramp)
+    })
+    .await;
+    let Some(mut ramp) = ramp? else {
+        return Ok(());
+    };
+    loop {
+        tokio::time::sleep_until(Instant::from_std(ramp.next_update())).await;
+        if cancel.is_cancelled() {
+            return Ok(());
+        }
+        let stepped;
+        (device, ramp, stepped) = blocking(move || {
+            let stepped = ramp.step(&*device);
+            (device, ramp, stepped)
+        })
+        .await;
+        if !stepped? {
+            return Ok(());
+        }
+    }
+}
+
+/// Run `f` on the blocking thread pool, and wait for it to return.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(f)
+        .await
+        .expect("device thread panicked")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::{set_brightness_all, spawn_ramp};
+    use crate::curve::Curve;
+    use crate::device::{Brightness, ReadNumResult, WriteResult};
+    use crate::ramp::{CancelToken, InterpolationOptions, Transition};
+    use crate::Error;
+
+    /// A device that can be moved to another task, and still be checked.
+    #[derive(Clone, Default)]
+    struct SharedDevice {
+        value: Arc<Mutex<u32>>,
+        written: Arc<Mutex<Vec<u32>>>,
+    }
+
+    impl Brightness for SharedDevice {
+        fn brightness(&self) -> ReadNumResult<u32> {
+            Ok(*self.value.lock().unwrap())
+        }
+
+        fn set_brightness(&self, value: u32) -> WriteResult {
+            *self.value.lock().unwrap() = value;
+            self.written.lock().unwrap().push(value);
+            Ok(())
+        }
+
+        fn max_brightness(&self) -> ReadNumResult<u32> {
+            Ok(100)
+        }
+    }
+
+    /// A device that cannot be written.
+    struct BrokenDevice;
+
+    impl Brightness for BrokenDevice {
+        fn brightness(&self) -> ReadNumResult<u32> {
+            Ok(0)
+        }
+
+        fn set_brightness(&self, _value: u32) -> WriteResult {
+            Err(std::io::ErrorKind::PermissionDenied.into())
+        }
+
+        fn max_brightness(&self) -> ReadNumResult<u32> {
+            Ok(100)
+        }
+    }
+
+    fn interpolate(frequency: u32) -> InterpolationOptions {
+        InterpolationOptions {
+            frequency: Some(frequency),
+            ..InterpolationOptions::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_ramps() {
+        let devices = [SharedDevice::default(), SharedDevice::default()];
+        let transitions = devices
+            .iter()
+            .zip([100, 50])
+            .map(|(device, target)| Transition {
+                path: PathBuf::new(),
+                device: Box::new(device.clone()),
+                target,
+                curve: Curve::Linear,
+                portion: target as f64 / 100.0,
+            })
+            .collect();
+        let interpolate = InterpolationOptions {
+            duration: Some("10m".parse().unwrap()),
+            ..interpolate(1)
+        };
+        let start = Instant::now();
+        set_brightness_all(transitions, &interpolate, &CancelToken::default())
+            .await
+            .unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(600));
+        for (device, target) in devices.iter().zip([100, 50]) {
+            let written = device.written.lock().unwrap();
+            assert_eq!(written.last(), Some(&target));
+            assert!(written.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_failed_ramp_stops_others() {
+        let device = SharedDevice::default();
+        // the broken device last, so that the other ramp is still running when it fails
+        let transitions = [Box::new(device.clone()) as _, Box::new(BrokenDevice) as _]
+            .into_iter()
+            .map(|device| Transition {
+                path: PathBuf::new(),
+                device,
+                target: 100,
+                curve: Curve::Linear,
+                portion: 1.0,
+            })
+            .collect();
+        let interpolate = InterpolationOptions {
+            duration: Some("10m".parse().unwrap()),
+            ..interpolate(1)
+        };
+        let start = Instant::now();
+        let result = set_brightness_all(transitions, &interpolate, &CancelToken::default()).await;
+        assert!(matches!(result, Err(Error::DeviceWriteFailed(..))));
+        assert!(start.elapsed() < Duration::from_secs(10));
+        let written = device.written.lock().unwrap().len();
+        tokio::time::sleep(Duration::from_secs(600)).await;
+        assert_eq!(device.written.lock().unwrap().len(), written);
+        assert!(device.brightness().unwrap() < 100);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancel_ramp() {
+        let device = SharedDevice::default();
+        let handle = spawn_ramp(
+            Box::new(device.clone()),
+            100,
+            Duration::from_secs(10),
+            &interpolate(10),
+            &Curve::Linear,
+            CancelToken::default(),
+        );
+        tokio::time::sleep(Duration::from_millis(5050)).await;
+        assert!(!handle.is_finished());
+        handle.cancel();
+        handle.await.unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(device.brightness().unwrap(), 50);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cancel_token() {
+        let device = SharedDevice::default();
+        let cancel = CancelToken::default();
+        let handle = spawn_ramp(
+            Box::new(device.clone()),
+            100,
+            Duration::from_secs(10),
+            &interpolate(10),
+            &Curve::Linear,
+            cancel.clone(),
+        );
+        cancel.cancel();
+        handle.await.unwrap();
+        assert!(device.written.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,226 @@
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::discovery::Sysfs;
+use crate::value::Value;
+
+const IIO_DEVICES: &str = "bus/iio/devices";
+/// Channels reporting illuminance in lux, either processed or raw.
+const ILLUMINANCE_CHANNELS: &[&str; 4] = &[
+    "in_illuminance_input",
+    "in_illuminance0_input",
+    "in_illuminance_raw",
+    "in_illuminance0_raw",
+];
+
+/// An ambient light sensor of the `iio` subsystem.
+///
+/// <https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-bus-iio>
+#[derive(Debug, Clone)]
+pub struct LightSensor {
+    /// The file of the illuminance channel.
+    channel: PathBuf,
+}
+
+impl LightSensor {
+    /// The first sensor in the `iio` subsystem with an illuminance channel.
+    pub fn find(sysfs: &Sysfs) -> Option<Self> {
+        let mut devices = sysfs
+            .join(IIO_DEVICES)
+            .read_dir()
+            .ok()?
+            .filter_map(|res| res.ok())
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        devices.sort();
+        devices.iter().find_map(|path| Self::new(path))
+    }
+
+    /// The sensor at `path`, if it has an illuminance channel.
+    pub fn new(path: &Path) -> Option<Self> {
+        ILLUMINANCE_CHANNELS
+            .iter()
+            .map(|channel| path.join(channel))
+            .find(|channel| channel.is_file())
+            .map(|channel| Self { channel })
+    }
+
+    /// The current illuminance in lux.
+    ///
+    /// Raw channels are converted with the `offset` and `scale` attributes
+    /// of the channel, or those shared by all illuminance channels.
+    pub fn illuminance(&self) -> std::io::Result<f64> {
+        let value = read_number(&self.channel)?;
+        let name = self.channel.file_name().and_then(|name| name.to_str());
+        let Some(prefix) = name.and_then(|name| name.strip_suffix("_raw")) else {
+            return Ok(value);
+        };
+        let attribute = |suffix: &str| {
+            [prefix, "in_illuminance"]
+                .iter()
+                .map(|prefix| self.channel.with_file_name(format!("{prefix}_{suffix}")))
+                .find(|path| path.is_file())
+                .map(|path| read_number(&path))
+                .transpose()
+        };
+        let offset = attribute("offset")?.unwrap_or(0.0);
+        let scale = attribute("scale")?.unwrap_or(1.0);
+        Ok((value + offset) * scale)
+    }
+}
+
+fn read_number(path: &Path) -> std::io::Result<f64> {
+    std::fs::read_to_string(path)?
+        .trim()
+        .parse()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// An exponential moving average of sensor readings, so that
+/// brief changes such as passing shadows are mostly ignored.
+#[derive(Debug, Clone)]
+pub struct Smoother {
+    /// The weight of each new reading, between 0 and 1.
+    factor: f64,
+    average: Option<f64>,
+}
+
+impl Smoother {
+    pub fn new(factor: f64) -> Self {
+        Self {
+            factor: factor.clamp(0.0, 1.0),
+            average: None,
+        }
+    }
+
+    /// Add a reading, and return the new average.
+    pub fn update(&mut self, value: f64) -> f64 {
+        let average = match self.average {
+            Some(average) => average + self.factor * (value - average),
+            None => value,
+        };
+        self.average = Some(average);
+        average
+    }
+}
+
+/// A piecewise-linear mapping from illuminance in lux to a brightness
+/// percentage, written as a list of `[LUX, "PERCENT%"]` points.
+/// Illuminance beyond the first or last point uses that point's brightness.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "Vec<(f64, Value)>")]
+pub struct LuxCurve {
+    /// Illuminance and perceived brightness as a fraction, by increasing illuminance.
+    points: Vec<(f64, f32)>,
+}
+
+impl Default for LuxCurve {
+    fn default() -> Self {
+        Self {
+            points: vec![
+                (0.0, 0.1),
+                (10.0, 0.25),
+                (100.0, 0.5),
+                (1000.0, 0.8),
+                (10000.0, 1.0),
+            ],
+        }
+    }
+}
+
+impl TryFrom<Vec<(f64, Value)>> for LuxCurve {
+    type Error = &'static str;
+
+    fn try_from(points: Vec<(f64, Value)>) -> Result<Self, Self::Error> {
+        if points.is_empty() {
+            return Err("the lux curve needs at least one point");
+        }
+        if points.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+            return Err("the illuminance of the lux curve must be increasing");
+        }
+        let points = points
+            .into_iter()
+            .map(|(lux, value)| match value {
+                Value::Percent(pct) => Ok((lux, pct)),
+                Value::Absolute(_) => Err("the brightness of the lux curve must be percentages"),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { points })
+    }
+}
+
+impl LuxCurve {
+    /// The brightness for an illuminance.
+    pub fn to_value(&self, lux: f64) -> Value {
+        let index = self.points.partition_point(|&(x, _)| x <= lux);
+        let pct = match (
+            self.points.get(index.wrapping_sub(1)),
+            self.points.get(index),
+        ) {
+            (Some(&(x0, y0)), Some(&(x1, y1))) => y0 + (y1 - y0) * ((lux - x0) / (x1 - x0)) as f32,
+            (Some(&(_, y)), None) | (None, Some(&(_, y))) => y,
+            (None, None) => unreachable!("the lux curve is never empty"),
+        };
+        Value::Percent(pct)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::{LightSensor, LuxCurve, Smoother};
+    use crate::value::Value;
+
+    #[test]
+    fn test_raw_sensor_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let device = dir.path().join("iio:device0");
+        std::fs::create_dir(&device).unwrap();
+        std::fs::write(device.join("name"), "als\n").unwrap();
+        std::fs::write(device.join("in_illuminance_raw"), "100\n").unwrap();
+        std::fs::write(device.join("in_illuminance_offset"), "10\n").unwrap();
+        std::fs::write(device.join("in_illuminance_scale"), "0.5\n").unwrap();
+
+        let sensor = LightSensor::new(&device).unwrap();
+        assert_eq!(sensor.illuminance().unwrap(), 55.0);
+        std::fs::write(device.join("in_illuminance_raw"), "990\n").unwrap();
+        assert_eq!(sensor.illuminance().unwrap(), 500.0);
+    }
+
+    #[test]
+    fn test_processed_sensor() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("in_illuminance0_input"), "123.5\n").unwrap();
+        std::fs::write(dir.path().join("in_illuminance_scale"), "10\n").unwrap();
+        let sensor = LightSensor::new(dir.path()).unwrap();
+        assert_eq!(sensor.illuminance().unwrap(), 123.5);
+        assert!(LightSensor::new(&dir.path().join("missing")).is_none());
+    }
+
+    #[test]
+    fn test_smoother() {
+        let mut smoother = Smoother::new(0.5);
+        let averages = [100.0, 200.0, 200.0, 0.0].map(|value| smoother.update(value));
+        assert_eq!(averages, [100.0, 150.0, 175.0, 87.5]);
+    }
+
+    #[test_case(0.0 => "10%")]
+    #[test_case(5.0 => "17.5%")]
+    #[test_case(550.0 => "65%")]
+    #[test_case(10000.0 => "100%")]
+    #[test_case(50000.0 => "100%")]
+    #[test_case(-1.0 => "10%")]
+    fn test_lux_curve(lux: f64) -> String {
+        LuxCurve::default().to_value(lux).to_string()
+    }
+
+    #[test]
+    fn test_invalid_lux_curve() {
+        let decreasing = vec![(10.0, Value::Percent(0.1)), (5.0, Value::Percent(0.2))];
+        assert!(LuxCurve::try_from(decreasing).is_err());
+        assert!(LuxCurve::try_from(vec![(0.0, Value::Absolute(10))]).is_err());
+        assert!(LuxCurve::try_from(vec![]).is_err());
+    }
+}
@@ -1,34 +1,50 @@
-use std::num::{ParseFloatError, ParseIntError};
-use std::ops::Deref;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::time::Duration;
 
-use bpaf::Bpaf;
+use bpaf::{construct, long, short, Bpaf, Parser};
+use slight::color::Color;
+use slight::curve::CurveArgument;
+use slight::easing::Easing;
+use slight::ramp::{ExternalChange, InterpolationOptions};
+use slight::value::{DurationArgument, ParseDurationError, ParseValueError, Value};
+use strum::EnumString;
 
 /// Small CLI utility for Linux to control brightness on ACPI devices.
 #[derive(Debug, PartialEq, Bpaf)]
 #[bpaf(options)]
 pub struct SlightCommand {
-    /// Show errors
-    #[bpaf(short('v'), long)]
-    pub verbose: bool,
-    /// The device to control
-    #[bpaf(short('D'), long, argument("DEVICE"))]
-    pub device: Option<PathBuf>,
+    /// The device to control, as a name, path, or glob pattern (repeatable)
+    #[bpaf(short('D'), long, argument("DEVICE"), many)]
+    pub device: Vec<String>,
+    /// How percentages map to brightness: linear, gamma[:EXP], cie, log[:BASE],
+    /// or file:PATH, optionally only for devices matching a DEVICE= prefix (repeatable)
+    #[bpaf(short('c'), long, argument("[DEVICE=]CURVE"), many)]
+    pub curve: Vec<CurveArgument>,
+    /// Always write brightness through systemd-logind instead of sysfs
+    #[bpaf(long)]
+    pub logind: bool,
+    /// The root of sysfs, instead of /sys or $SLIGHT_SYSFS_ROOT
+    #[bpaf(long, argument("PATH"), optional)]
+    pub sysfs_root: Option<PathBuf>,
+    /// Run the command in this process, even if the daemon is running
+    #[bpaf(long)]
+    pub no_daemon: bool,
     /// What to do?
     #[bpaf(external(action))]
     pub command: Action,
 }
 
-#[derive(Debug, PartialEq, Bpaf)]
+#[derive(Clone, Debug, PartialEq, Bpaf)]
 pub enum Action {
     /// Discover and list all backlight devices
     #[bpaf(command("list"))]
     List {
-        /// List devices as full paths (not names)
+        /// List devices as full paths (not names), without marking the default
         #[bpaf(short('P'), long)]
         paths: bool,
+        /// Print the state of every device as JSON
+        #[bpaf(long)]
+        json: bool,
     },
     /// Get the current brightness of DEVICE
     #[bpaf(command("get"))]
@@ -36,6 +52,9 @@ pub enum Action {
         /// Show the brightness as a percentage
         #[bpaf(short('p'), long)]
         percent: bool,
+        /// Print the state of DEVICE as JSON
+        #[bpaf(long)]
+        json: bool,
     },
     /// Set the brightness of DEVICE to VALUE
     #[bpaf(command("set"))]
@@ -71,189 +90,310 @@ pub enum Action {
         #[bpaf(positional("AMOUNT"))]
         amount: Value,
     },
+    /// Keep devices open and accept commands from other invocations
+    #[bpaf(command("daemon"))]
+    Daemon,
+    /// Save the brightness of DEVICE, to be restored later
+    #[bpaf(command("save"))]
+    Save {
+        /// Save every device
+        #[bpaf(short('a'), long)]
+        all: bool,
+        /// The file to save to, instead of the default state file
+        #[bpaf(long, argument("FILE"), optional)]
+        file: Option<PathBuf>,
+    },
+    /// Restore the brightness of DEVICE saved by `save`
+    #[bpaf(command("restore"))]
+    Restore {
+        /// Restore every saved device
+        #[bpaf(short('a'), long)]
+        all: bool,
+        /// The file to restore from, instead of the default state file
+        #[bpaf(long, argument("FILE"), optional)]
+        file: Option<PathBuf>,
+        #[bpaf(external(interpolation_options))]
+        interpolate: InterpolationOptions,
+    },
+    /// Turn the backlight of DEVICE on or off, or show whether it is on
+    #[bpaf(command("power"))]
+    Power {
+        /// Fade the brightness out before turning off, and in after turning on
+        #[bpaf(long)]
+        fade: bool,
+        #[bpaf(external(interpolation_options))]
+        interpolate: InterpolationOptions,
+        /// One of: on, off, toggle, status
+        #[bpaf(positional("STATE"))]
+        state: PowerCommand,
+    },
+    /// Set the color of the multicolor LED DEVICE
+    #[bpaf(command("color"))]
+    Color {
+        /// The overall brightness to set along with the color
+        #[bpaf(short('b'), long, argument("VALUE"), optional)]
+        brightness: Option<Value>,
+        #[bpaf(external(interpolation_options))]
+        interpolate: InterpolationOptions,
+        /// A hex color like #ff8800, the intensity of every channel
+        /// like 255,136,0, or of some channels like red=255,blue=0
+        #[bpaf(positional("COLOR"))]
+        color: Color,
+    },
+    /// Blink DEVICE on and off, until interrupted or for a number of cycles
+    #[bpaf(command("blink"))]
+    Blink {
+        #[bpaf(external(effect_options))]
+        effect: EffectOptions,
+    },
+    /// Fade DEVICE in and out, until interrupted or for a number of cycles
+    #[bpaf(command("pulse"))]
+    Pulse {
+        #[bpaf(external(effect_options))]
+        effect: EffectOptions,
+        /// The maximum frequency of brightness updates (Hz), 30 by default
+        #[bpaf(
+            long("freq"),
+            long("frequency"),
+            argument("FREQUENCY"),
+            guard(is_positive, NO_FREQUENCY),
+            optional
+        )]
+        frequency: Option<u32>,
+        /// The rate of change of each fade: linear, ease-in, ease-out,
+        /// ease-in-out (default), cubic, or exponential
+        #[bpaf(long, argument("EASING"), fallback(Easing::EaseInOut))]
+        easing: Easing,
+    },
+    /// Run a pattern on the LED DEVICE, with its pattern trigger if it has one,
+    /// or else in this process until interrupted
+    #[bpaf(command("pattern"))]
+    Pattern {
+        /// Fade the brightness of each step to that of the next, instead of holding it
+        #[bpaf(long)]
+        fade: bool,
+        /// The number of times to run the pattern, instead of forever
+        #[bpaf(
+            short('n'),
+            long,
+            argument("COUNT"),
+            guard(is_positive, NO_CYCLES),
+            optional
+        )]
+        cycles: Option<u32>,
+        /// The brightness of each step, and how long it lasts, like 100%:500ms
+        #[bpaf(
+            positional("VALUE:DURATION"),
+            some("a pattern needs at least one step")
+        )]
+        steps: Vec<PatternStep>,
+    },
+    /// Show or change the trigger of the LED DEVICE
+    #[bpaf(command("trigger"))]
+    Trigger {
+        #[bpaf(external(trigger_command))]
+        command: TriggerCommand,
+    },
+    /// Adjust the brightness of DEVICE to the ambient light, until killed
+    #[bpaf(command("auto"))]
+    Auto {
+        /// The directory of the iio light sensor to read
+        #[bpaf(long, argument("PATH"), optional)]
+        sensor: Option<PathBuf>,
+        /// How often to read the sensor
+        #[bpaf(long, argument("DURATION"), guard(is_nonzero, NO_INTERVAL), optional)]
+        interval: Option<DurationArgument>,
+        /// Adjust the brightness once, and exit
+        #[bpaf(long)]
+        once: bool,
+        #[bpaf(external(interpolation_options))]
+        interpolate: InterpolationOptions,
+    },
+    /// Change the brightness of DEVICE in response to events from other programs
+    #[bpaf(command("hook"))]
+    Hook {
+        #[bpaf(external(hook))]
+        hook: Hook,
+    },
 }
 
-#[derive(Debug, PartialEq, Bpaf)]
-pub struct InterpolationOptions {
-    /// Maximum duration of time over which to interpolate the change
-    #[bpaf(
-        short('t'),
-        long,
-        argument("DURATION"),
-        fallback(DurationArgument::ZERO)
-    )]
-    pub duration: DurationArgument,
-    /// The maximum frequency of brightness updates (Hz)
-    #[bpaf(long("freq"), long("frequency"), argument("FREQUENCY"), fallback(30))]
-    pub frequency: u32,
+#[derive(Clone, Debug, PartialEq, Bpaf)]
+pub enum Hook {
+    /// Called by Gammastep or Redshift, with the periods configured in `[hook.gammastep]`
+    #[bpaf(command("gammastep"))]
+    Gammastep {
+        /// The event, such as `period-changed`
+        #[bpaf(positional("EVENT"))]
+        event: String,
+        /// The arguments of the event, such as the old and new periods
+        #[bpaf(positional("ARGS"), many)]
+        args: Vec<String>,
+    },
 }
 
-#[derive(Clone, Debug, PartialEq, thiserror::Error)]
-pub enum ParseValueError {
-    #[error("percentage '{0}' must be between 0 and 100")]
-    PercentOutOfRange(u8),
-    #[error("{0} for percentage '{1}'")]
-    ParsePercentError(ParseIntError, String),
-    #[error("{0} for absolute value '{1}'")]
-    ParseAbsoluteError(ParseIntError, String),
+/// The options of every command that ramps the brightness.
+pub fn interpolation_options() -> impl Parser<InterpolationOptions> {
+    let duration = short('t')
+        .long("duration")
+        .help("Maximum duration of time over which to interpolate the change")
+        .argument::<DurationArgument>("DURATION")
+        .optional();
+    let frequency = long("freq")
+        .long("frequency")
+        .help("The maximum frequency of brightness updates (Hz), 30 by default")
+        .argument::<u32>("FREQUENCY")
+        .guard(is_positive, NO_FREQUENCY)
+        .optional();
+    let easing = long("easing")
+        .help(
+            "The rate of change over the duration: linear, ease-in, ease-out,\n\
+             ease-in-out, cubic, or exponential",
+        )
+        .argument::<Easing>("EASING")
+        .fallback(Easing::Linear);
+    let external_change = external_change().fallback(ExternalChange::Yield);
+    construct!(InterpolationOptions {
+        duration,
+        frequency,
+        easing,
+        external_change,
+    })
 }
 
-#[derive(PartialEq, Debug, Copy, Clone)]
-pub enum Value {
-    Percent(f32),
-    Absolute(u32),
+fn external_change() -> impl Parser<ExternalChange> {
+    let yield_ = long("yield")
+        .help("Stop the ramp when the brightness is changed externally (default)")
+        .req_flag(ExternalChange::Yield);
+    let force = long("force")
+        .help("Continue the ramp even if the brightness is changed externally")
+        .req_flag(ExternalChange::Force);
+    construct!([yield_, force]).group_help(
+        "What to do when the brightness is changed by something else during a ramp,\n\
+         such as a brightness key.",
+    )
 }
 
-impl Value {
-    pub fn to_percent(self, max: u32) -> f32 {
-        match self {
-            Value::Percent(pct) => pct,
-            Value::Absolute(abs) => abs as f32 / max as f32,
-        }
-    }
-
-    pub fn to_absolute(self, max: u32) -> u32 {
-        match self {
-            Value::Percent(pct) => (pct.clamp(0.0, 1.0) * max as f32).round() as u32,
-            Value::Absolute(abs) => abs,
-        }
-        .min(max)
-    }
-
-    pub fn as_percent(self, max: u32) -> Self {
-        Value::Percent(self.to_percent(max))
-    }
-
-    pub fn as_absolute(self, max: u32) -> Self {
-        Value::Absolute(self.to_absolute(max))
-    }
-
-    pub fn saturating_add(lhs: u32, rhs: Self, max: u32) -> u32 {
-        lhs.saturating_add(rhs.to_absolute(max)).min(max)
-    }
+const NO_CYCLES: &str = "the number of cycles must be at least one";
+const NO_FREQUENCY: &str = "the frequency must be greater than zero";
+const NO_INTERVAL: &str = "the interval must be greater than zero";
 
-    pub fn saturating_sub(lhs: u32, rhs: Self, max: u32) -> u32 {
-        lhs.saturating_sub(rhs.to_absolute(max))
-    }
+fn is_positive(value: &u32) -> bool {
+    *value > 0
 }
 
-impl FromStr for Value {
-    type Err = ParseValueError;
-
-    fn from_str(value: &str) -> Result<Self, Self::Err> {
-        use ParseValueError as E;
-
-        let value = value.trim();
-        if value.ends_with('%') {
-            let value = value[0..value.len() - 1]
-                .parse::<u8>()
-                .map_err(|e| E::ParsePercentError(e, value.to_string()))?;
-            if !(0..=100).contains(&value) {
-                Err(E::PercentOutOfRange(value))
-            } else {
-                Ok(Self::Percent(value as f32 / 100.0))
-            }
-        } else {
-            let value = value
-                .parse::<u32>()
-                .map_err(|e| E::ParseAbsoluteError(e, value.to_string()))?;
-            Ok(Self::Absolute(value))
-        }
-    }
+fn is_nonzero(duration: &DurationArgument) -> bool {
+    !duration.is_zero()
 }
 
-impl std::fmt::Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Value::Percent(pct) => write!(f, "{}%", pct * 100.0),
-            Value::Absolute(abs) => write!(f, "{abs}"),
-        }
-    }
+/// The brightness and timing of `blink` and `pulse`.
+#[derive(Clone, Debug, PartialEq, Bpaf)]
+pub struct EffectOptions {
+    /// The brightness when on, the maximum by default
+    #[bpaf(long, argument("VALUE"), optional)]
+    pub on: Option<Value>,
+    /// The brightness when off, zero by default
+    #[bpaf(long, argument("VALUE"), optional)]
+    pub off: Option<Value>,
+    /// The duration of each cycle, one second by default
+    #[bpaf(short('t'), long, argument("DURATION"), optional)]
+    pub period: Option<DurationArgument>,
+    /// The number of cycles, instead of until interrupted
+    #[bpaf(
+        short('n'),
+        long,
+        argument("COUNT"),
+        guard(is_positive, NO_CYCLES),
+        optional
+    )]
+    pub cycles: Option<u32>,
 }
 
-/// A wrapper of [`Duration`] that is non-zero and implements [`FromStr`].
-#[derive(Clone, Debug, PartialEq)]
-pub struct DurationArgument(pub Duration);
-
-impl DurationArgument {
-    pub const ZERO: Self = DurationArgument(Duration::ZERO);
+#[derive(Clone, Debug, PartialEq, Bpaf)]
+pub enum TriggerCommand {
+    /// List the triggers of DEVICE, marking the current one
+    #[bpaf(command("list"))]
+    List,
+    /// Show the current trigger of DEVICE, and its attributes
+    #[bpaf(command("get"))]
+    Get,
+    /// Set the trigger of DEVICE, and its attributes
+    #[bpaf(command("set"))]
+    Set {
+        /// Milliseconds on, for the timer trigger
+        #[bpaf(long, argument("MS"), optional)]
+        delay_on: Option<u32>,
+        /// Milliseconds off, for the timer trigger
+        #[bpaf(long, argument("MS"), optional)]
+        delay_off: Option<u32>,
+        /// The network interface, for the netdev trigger
+        #[bpaf(long, argument("INTERFACE"), optional)]
+        device_name: Option<String>,
+        /// Show the link state, for the netdev trigger
+        #[bpaf(long, argument::<Switch>("on|off"), map(Switch::is_on), optional)]
+        link: Option<bool>,
+        /// Blink when receiving, for the netdev trigger
+        #[bpaf(long, argument::<Switch>("on|off"), map(Switch::is_on), optional)]
+        rx: Option<bool>,
+        /// Blink when transmitting, for the netdev trigger
+        #[bpaf(long, argument::<Switch>("on|off"), map(Switch::is_on), optional)]
+        tx: Option<bool>,
+        /// The name of the trigger
+        #[bpaf(positional("TRIGGER"))]
+        trigger: String,
+    },
 }
 
-impl Deref for DurationArgument {
-    type Target = Duration;
+/// A setting of a trigger, turned `on` or `off`.
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Switch {
+    On,
+    Off,
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl Switch {
+    fn is_on(self) -> bool {
+        self == Self::On
     }
 }
 
-impl FromStr for DurationArgument {
-    type Err = ParseDurationError;
+#[derive(Clone, Copy, Debug, PartialEq, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum PowerCommand {
+    On,
+    Off,
+    Toggle,
+    Status,
+}
 
-    fn from_str(value: &str) -> Result<Self, Self::Err> {
-        let value = value.trim();
-        match parse_duration(value) {
-            Ok(dur) => Ok(Self(dur)),
-            Err(ParseDurationError::MissingSuffix) => {
-                let ms = value.parse().map_err(ParseDurationError::ParseIntError)?;
-                Ok(Self(Duration::from_millis(ms)))
-            }
-            Err(e) => Err(e),
-        }
-    }
+/// A step of `slight pattern`, written as `VALUE:DURATION`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PatternStep {
+    pub value: Value,
+    pub duration: DurationArgument,
 }
 
 #[derive(Clone, Debug, PartialEq, thiserror::Error)]
-pub enum ParseDurationError {
-    #[error("duration is missing a value")]
-    MissingNumber,
-    #[error("durations cannot be negative")]
-    NegativeNumber,
-    #[error("duration is missing a suffix")]
-    MissingSuffix,
-    #[error("unknown suffix '{0}' for duration, must be one of: `ms`, `ds`, `s`, `m`")]
-    InvalidSuffix(String),
-    #[error("{0} for duration")]
-    ParseFloatError(#[from] ParseFloatError),
-    #[error("{0} for duration")]
-    ParseIntError(#[from] ParseIntError),
+pub enum ParsePatternStepError {
+    #[error("pattern step '{0}' must be written as VALUE:DURATION")]
+    MissingDuration(String),
+    #[error("{0}")]
+    Value(#[from] ParseValueError),
+    #[error("{0}")]
+    Duration(#[from] ParseDurationError),
 }
 
-fn parse_duration(value: &str) -> Result<Duration, ParseDurationError> {
-    use ParseDurationError as E;
-
-    if value.is_empty() {
-        return Err(E::MissingNumber);
-    }
-
-    macro_rules! parse_with_suffix {
-        ($suffix:literal, $parse_ty:ty, $map:expr) => {
-            if value.ends_with($suffix) {
-                let number = &value[0..value.len() - $suffix.len()];
-                if number.is_empty() {
-                    return Err(E::MissingNumber);
-                }
-                let number = number.parse::<$parse_ty>()?;
-                if number < 0 as $parse_ty {
-                    return Err(E::NegativeNumber);
-                } else {
-                    return Ok($map(number));
-                }
-            }
-        };
-    }
-
-    parse_with_suffix!("ms", u64, Duration::from_millis);
-    parse_with_suffix!("ds", u64, |ds| Duration::from_millis(ds * 100));
-    parse_with_suffix!("s", f64, Duration::from_secs_f64);
-    parse_with_suffix!("m", f64, |m| Duration::from_secs_f64(m * 60.0));
+impl FromStr for PatternStep {
+    type Err = ParsePatternStepError;
 
-    let number = value.trim_end_matches(|ch: char| !ch.is_numeric() && ch != '.');
-    let suffix = &value[number.len()..];
-    if suffix.is_empty() {
-        Err(E::MissingSuffix)
-    } else {
-        Err(E::InvalidSuffix(suffix.to_string()))
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (brightness, duration) = value
+            .split_once(':')
+            .ok_or_else(|| ParsePatternStepError::MissingDuration(value.to_owned()))?;
+        Ok(Self {
+            value: brightness.parse()?,
+            duration: duration.parse()?,
+        })
     }
 }
 
@@ -261,35 +401,36 @@ fn parse_duration(value: &str) -> Result<Duration, ParseDurationError> {
 mod tests {
     use std::time::Duration;
 
-    use test_case::{test_case, test_matrix};
+    use test_case::test_case;
+
+    use slight::value::Value;
 
-    use super::{parse_duration, slight_command, ParseDurationError};
+    use super::{slight_command, PatternStep};
 
     #[test]
     fn bpaf_check_invariants() {
         slight_command().check_invariants(false);
     }
 
-    #[test_case("100ms" => Duration::from_millis(100))]
-    #[test_case("10ds" => Duration::from_secs_f64(1.0))]
-    #[test_case("1s" => Duration::from_secs(1))]
-    #[test_case("1m" => Duration::from_secs(60))]
-    #[test_case("1.0s" => Duration::from_secs_f64(1.0))]
-    #[test_case("1.0m" => Duration::from_secs_f64(60.0))]
-    fn test_parse_duration(input: &str) -> Duration {
-        parse_duration(input).unwrap()
+    #[test_case("100%:500ms" => Ok((Value::Percent(1.0), Duration::from_millis(500))))]
+    #[test_case("20:1s" => Ok((Value::Absolute(20), Duration::from_secs(1))))]
+    #[test_case("20" => Err("pattern step '20' must be written as VALUE:DURATION".to_owned()))]
+    #[test_case("20:1h" => Err("unknown suffix 'h' for duration, must be one of: `ms`, `ds`, `s`, `m`".to_owned()))]
+    fn test_parse_pattern_step(input: &str) -> Result<(Value, Duration), String> {
+        input
+            .parse::<PatternStep>()
+            .map(|step| (step.value, *step.duration))
+            .map_err(|e| e.to_string())
     }
 
-    #[test_matrix(["ms", "ds", "s", "m"] => ParseDurationError::MissingNumber)]
-    #[test_matrix(["-1.0s", "-1.0m"] => ParseDurationError::NegativeNumber)]
-    #[test_matrix(["1.0", "1"] => ParseDurationError::MissingSuffix)]
-    #[test_matrix(["1h", "h"] => ParseDurationError::InvalidSuffix("h".to_owned()))]
-    #[test_case("100.0ms" => ParseDurationError::ParseIntError("100.0".parse::<u64>().unwrap_err()))]
-    #[test_case("10.0ms" => ParseDurationError::ParseIntError("10.0".parse::<u64>().unwrap_err()))]
-    #[test_case("-100ms" => ParseDurationError::ParseIntError("-100".parse::<u64>().unwrap_err()))]
-    #[test_case("0x01s" => ParseDurationError::ParseFloatError("0x01".parse::<f64>().unwrap_err()))]
-    #[test_case("" => ParseDurationError::MissingNumber)]
-    fn test_parse_duration_error(input: &str) -> ParseDurationError {
-        parse_duration(input).unwrap_err()
+    #[test_case(&["blink", "-n", "1"] => true)]
+    #[test_case(&["blink", "-n", "0"] => false)]
+    #[test_case(&["pattern", "-n", "0", "100%:1s"] => false)]
+    #[test_case(&["set", "50%", "--freq", "0"] => false)]
+    #[test_case(&["pulse", "--freq", "0"] => false)]
+    #[test_case(&["pulse", "--freq", "1"] => true)]
+    #[test_case(&["auto", "--interval", "0s"] => false)]
+    fn test_parse_positive(args: &[&str]) -> bool {
+        slight_command().run_inner(args).is_ok()
     }
 }
@@ -0,0 +1,78 @@
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Where ramps get the time from, and how they wait for the next update.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    /// Block until `deadline`, returning immediately if it has passed.
+    fn sleep_until(&self, deadline: Instant);
+}
+
+/// The real time, as given by [`Instant::now`] and [`std::thread::sleep`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+/// A clock that only moves when something sleeps on it, where it jumps
+/// straight to the deadline, so that a ramp of any duration runs instantly.
+#[derive(Debug)]
+pub struct VirtualClock {
+    now: Mutex<Instant>,
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        let now = Instant::now();
+        if now < deadline {
+            std::thread::sleep(deadline - now);
+        }
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+impl VirtualClock {
+    /// Move the time forward, as if it passed without anything sleeping.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += duration;
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn sleep_until(&self, deadline: Instant) {
+        let mut now = self.now.lock().unwrap_or_else(PoisonError::into_inner);
+        *now = (*now).max(deadline);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Clock, VirtualClock};
+
+    #[test]
+    fn test_virtual_clock() {
+        let clock = VirtualClock::default();
+        let start = clock.now();
+        clock.sleep_until(start + Duration::from_secs(3600));
+        assert_eq!(clock.now() - start, Duration::from_secs(3600));
+        // the deadline has passed, so this does not go back in time
+        clock.sleep_until(start);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(clock.now() - start, Duration::from_secs(3601));
+    }
+}
@@ -0,0 +1,268 @@
+use std::cell::Cell;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::device::{Brightness, Multicolor, ReadNumResult, WriteResult};
+use crate::BoxedDevice;
+
+/// The number of steps of a [`ColorFade`], which is
+/// far more than any ramp will write.
+const FADE_STEPS: u32 = 10000;
+const RGB_CHANNELS: &[&str; 3] = &["red", "green", "blue"];
+
+/// A color for a multicolor LED.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Color {
+    /// A hex color such as `#ff8800`, for LEDs with
+    /// red, green, and blue channels. Other channels are turned off.
+    Rgb([u8; 3]),
+    /// Intensities of every channel, in the order of `multi_index`, such as `255,136,0`.
+    Intensities(Vec<u32>),
+    /// Intensities of some channels, by name, such as `red=255,blue=0`.
+    /// Other channels keep their intensity.
+    Channels(Vec<(String, u32)>),
+}
+
+#[derive(Clone, Debug, PartialEq, Error)]
+pub enum ParseColorError {
+    #[error("hex color '{0}' must have six digits")]
+    HexLength(String),
+    #[error("{0} for color '{1}'")]
+    ParseIntError(ParseIntError, String),
+    #[error("color '{0}' mixes named and unnamed channels")]
+    MixedChannels(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Error)]
+pub enum ColorError {
+    #[error("a hex color requires red, green, and blue channels, but the LED has: {0}")]
+    NotRgb(String),
+    #[error("expected {0} intensities, one for each channel: {1}")]
+    WrongCount(usize, String),
+    #[error("unknown channel '{0}', must be one of: {1}")]
+    UnknownChannel(String, String),
+}
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use ParseColorError as E;
+
+        let value = value.trim();
+        let parse_int = |int: &str, radix| {
+            u32::from_str_radix(int.trim(), radix)
+                .map_err(|e| E::ParseIntError(e, value.to_owned()))
+        };
+        if let Some(hex) = value.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return Err(E::HexLength(value.to_owned()));
+            }
+            let mut rgb = [0; 3];
+            for (channel, digits) in rgb.iter_mut().zip(hex.as_bytes().chunks(2)) {
+                // the digits are ASCII, so this is still valid UTF-8
+                *channel = parse_int(std::str::from_utf8(digits).unwrap(), 16)? as u8;
+            }
+            return Ok(Self::Rgb(rgb));
+        }
+
+        let parts = value.split(',').collect::<Vec<_>>();
+        if parts.iter().all(|part| part.contains('=')) {
+            parts
+                .iter()
+                .map(|part| {
+                    let (name, int) = part.split_once('=').unwrap();
+                    Ok((name.trim().to_owned(), parse_int(int, 10)?))
+                })
+                .collect::<Result<_, _>>()
+                .map(Self::Channels)
+        } else if parts.iter().any(|part| part.contains('=')) {
+            Err(E::MixedChannels(value.to_owned()))
+        } else {
+            parts
+                .iter()
+                .map(|part| parse_int(part, 10))
+                .collect::<Result<_, _>>()
+                .map(Self::Intensities)
+        }
+    }
+}
+
+impl Color {
+    /// The intensity of each channel in `index`, given their `current`
+    /// intensities, and the maximum intensity of each channel.
+    pub fn intensities(
+        &self,
+        index: &[String],
+        current: &[u32],
+        max: u32,
+    ) -> Result<Vec<u32>, ColorError> {
+        let channels = || index.join(" ");
+        match self {
+            Color::Rgb(rgb) => {
+                if !RGB_CHANNELS
+                    .iter()
+                    .all(|name| index.iter().any(|c| c == name))
+                {
+                    return Err(ColorError::NotRgb(channels()));
+                }
+                Ok(index
+                    .iter()
+                    .map(
+                        |name| match RGB_CHANNELS.iter().position(|rgb| rgb == name) {
+                            Some(i) => (rgb[i] as f64 * max as f64 / 255.0).round() as u32,
+                            None => 0,
+                        },
+                    )
+                    .collect())
+            }
+            Color::Intensities(intensities) => {
+                if intensities.len() != index.len() {
+                    return Err(ColorError::WrongCount(index.len(), channels()));
+                }
+                Ok(intensities.iter().map(|&value| value.min(max)).collect())
+            }
+            Color::Channels(named) => {
+                let mut intensities = current.to_vec();
+                for (name, value) in named {
+                    let i = index
+                        .iter()
+                        .position(|channel| channel == name)
+                        .ok_or_else(|| ColorError::UnknownChannel(name.clone(), channels()))?;
+                    intensities[i] = (*value).min(max);
+                }
+                Ok(intensities)
+            }
+        }
+    }
+}
+
+/// A change of both the color and brightness of a multicolor LED, which
+/// can be ramped like the brightness of any device. The brightness of this
+/// is the progress of the change, from zero to [`ColorFade::target`].
+pub struct ColorFade<L: Multicolor> {
+    led: L,
+    /// The device that the overall brightness is written to, which may
+    /// write through logind.
+    device: BoxedDevice,
+    intensity: (Vec<u32>, Vec<u32>),
+    brightness: (u32, u32),
+    step: Cell<u32>,
+}
+
+impl<L: Multicolor> ColorFade<L> {
+    pub fn new(
+        led: L,
+        device: BoxedDevice,
+        intensity: (Vec<u32>, Vec<u32>),
+        brightness: (u32, u32),
+    ) -> Self {
+        Self {
+            led,
+            device,
+            intensity,
+            brightness,
+            step: Cell::new(0),
+        }
+    }
+
+    /// The progress when the change is complete.
+    pub fn target(&self) -> u32 {
+        FADE_STEPS
+    }
+}
+
+fn lerp(from: u32, to: u32, progress: f64) -> u32 {
+    (from as f64 + (to as f64 - from as f64) * progress).round() as u32
+}
+
+impl<L: Multicolor> Brightness for ColorFade<L> {
+    fn brightness(&self) -> ReadNumResult<u32> {
+        Ok(self.step.get())
+    }
+
+    fn set_brightness(&self, step: u32) -> WriteResult {
+        let progress = step as f64 / FADE_STEPS as f64;
+        let (from, to) = &self.intensity;
+        let intensity = from
+            .iter()
+            .zip(to)
+            .map(|(&from, &to)| lerp(from, to, progress))
+            .collect::<Vec<_>>();
+        self.led.set_multi_intensity(&intensity)?;
+        let (from, to) = self.brightness;
+        if from != to {
+            self.device.set_brightness(lerp(from, to, progress))?;
+        }
+        self.step.set(step);
+        Ok(())
+    }
+
+    fn max_brightness(&self) -> ReadNumResult<u32> {
+        Ok(FADE_STEPS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::{Color, ColorError, ColorFade};
+    use crate::device::{Brightness, LedDevice, Multicolor};
+
+    #[test_case("#ff8800" => Color::Rgb([255, 136, 0]))]
+    #[test_case("#FF8800" => Color::Rgb([255, 136, 0]); "uppercase hex")]
+    #[test_case("255,136,0" => Color::Intensities(vec![255, 136, 0]))]
+    #[test_case("red=255, blue=0" => Color::Channels(vec![("red".to_owned(), 255), ("blue".to_owned(), 0)]))]
+    fn test_parse_color(value: &str) -> Color {
+        value.parse().unwrap()
+    }
+
+    #[test_case("#ff88")]
+    #[test_case("#gg8800")]
+    #[test_case("red=255,0")]
+    #[test_case("red")]
+    fn test_parse_color_error(value: &str) {
+        assert!(value.parse::<Color>().is_err());
+    }
+
+    fn index(channels: &str) -> Vec<String> {
+        channels.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test_case("#ff8800", "red green blue" => Ok(vec![100, 53, 0]))]
+    #[test_case("#ff8800", "white blue green red" => Ok(vec![0, 0, 53, 100]))]
+    #[test_case("#ff8800", "red green" => Err(ColorError::NotRgb("red green".to_owned())))]
+    #[test_case("1,2", "red green blue" => Err(ColorError::WrongCount(3, "red green blue".to_owned())))]
+    #[test_case("1,200,3", "red green blue" => Ok(vec![1, 100, 3]))]
+    #[test_case("green=7", "red green blue" => Ok(vec![10, 7, 30]))]
+    #[test_case("white=7", "red green blue" => Err(ColorError::UnknownChannel("white".to_owned(), "red green blue".to_owned())))]
+    fn test_intensities(color: &str, channels: &str) -> Result<Vec<u32>, ColorError> {
+        let current = [10, 20, 30, 40];
+        let index = index(channels);
+        let color = color.parse::<Color>().unwrap();
+        color.intensities(&index, &current[..index.len()], 100)
+    }
+
+    #[test]
+    fn test_color_fade() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("brightness"), "0").unwrap();
+        std::fs::write(dir.path().join("max_brightness"), "100").unwrap();
+        std::fs::write(dir.path().join("multi_index"), "red green blue\n").unwrap();
+        std::fs::write(dir.path().join("multi_intensity"), "0 0 0\n").unwrap();
+
+        let led = LedDevice::new(dir.path().to_owned());
+        let device = Box::new(LedDevice::new(dir.path().to_owned()));
+        let fade = ColorFade::new(led, device, (vec![0, 0, 0], vec![100, 50, 0]), (0, 0));
+        fade.set_brightness(fade.target() / 2).unwrap();
+        assert_eq!(fade.brightness().unwrap(), fade.target() / 2);
+
+        let led = LedDevice::new(dir.path().to_owned());
+        assert_eq!(led.multi_intensity().unwrap(), [50, 25, 0]);
+        fade.set_brightness(fade.target()).unwrap();
+        assert_eq!(led.multi_intensity().unwrap(), [100, 50, 0]);
+    }
+}
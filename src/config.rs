@@ -0,0 +1,458 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+use thiserror::Error;
+use toml::de::{DeString, DeTable, DeValue};
+
+use crate::auto::LuxCurve;
+use crate::curve::{Curve, CurveArgument};
+use crate::discovery::DeviceDetail;
+use crate::value::{DurationArgument, Value};
+
+const CONFIG_FILE: &str = "slight/config.toml";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read config file '{0}': {1}")]
+    ReadFailed(PathBuf, std::io::Error),
+    #[error("malformed config file '{0}': {1}")]
+    Malformed(PathBuf, toml::de::Error),
+    #[error("invalid config file '{0}': frequency must be greater than zero")]
+    ZeroFrequency(PathBuf),
+    #[error("invalid config file '{0}': auto.interval must be greater than zero")]
+    ZeroInterval(PathBuf),
+    #[error("invalid config file '{0}': auto.smoothing must be from 0 to 1, not {1}")]
+    SmoothingOutOfRange(PathBuf, f64),
+    #[error("invalid config file '{0}': min is greater than max for device '{1}'")]
+    MinAboveMax(PathBuf, String),
+}
+
+/// The configuration file, `$XDG_CONFIG_HOME/slight/config.toml`.
+///
+/// Every section is optional, and missing settings use their defaults.
+/// Arguments given on the command line take precedence. The paths of
+/// `file:` curves are relative to the directory of the file.
+///
+/// ```toml
+/// device = "screen"
+/// curve = "cie"
+/// duration = "250ms"
+/// frequency = 60
+///
+/// [aliases]
+/// screen = "intel_backlight"
+/// kbd = "*::kbd_backlight"
+///
+/// [devices.screen]
+/// min = "5%"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The device to control when none are given, as a name, path, glob pattern, or alias.
+    pub device: Option<String>,
+    /// The curve for devices without their own.
+    pub curve: Option<Curve>,
+    /// The duration of ramps.
+    pub duration: Option<DurationArgument>,
+    /// The frequency of updates during ramps.
+    pub frequency: Option<u32>,
+    /// Short names for devices, which can be used anywhere a device pattern is accepted.
+    pub aliases: HashMap<String, String>,
+    /// Settings for the devices matching each name, glob pattern, or alias.
+    pub devices: BTreeMap<String, DeviceConfig>,
+    pub auto: AutoConfig,
+    pub hook: HookConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DeviceConfig {
+    /// The lowest brightness that will be set.
+    pub min: Option<Value>,
+    /// The highest brightness that will be set.
+    pub max: Option<Value>,
+    pub curve: Option<Curve>,
+}
+
+/// Settings for adjusting the brightness to the ambient light.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AutoConfig {
+    /// The `iio` device of the light sensor, instead of the first one found.
+    pub sensor: Option<PathBuf>,
+    /// How often the sensor is read.
+    pub interval: DurationArgument,
+    /// The weight of each new reading in the average, from 0 to 1.
+    /// Lower values react more slowly to changes in the light.
+    pub smoothing: f64,
+    pub curve: LuxCurve,
+}
+
+impl Default for AutoConfig {
+    fn default() -> Self {
+        Self {
+            sensor: None,
+            interval: DurationArgument(Duration::from_secs(1)),
+            smoothing: 0.3,
+            curve: LuxCurve::default(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HookConfig {
+    pub gammastep: GammastepHookConfig,
+}
+
+/// The brightness for each period of the day, and the durations of the
+/// ramps between them. The defaults match the home-manager module.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct GammastepHookConfig {
+    pub daytime: Value,
+    pub transition: Value,
+    pub night: Value,
+    pub daytime_from_transition: DurationArgument,
+    pub transition_from_daytime: DurationArgument,
+    pub night_from_transition: DurationArgument,
+    pub transition_from_night: DurationArgument,
+}
+
+impl Default for GammastepHookConfig {
+    fn default() -> Self {
+        Self {
+            daytime: Value::Percent(0.85),
+            transition: Value::Percent(0.55),
+            night: Value::Percent(0.25),
+            daytime_from_transition: DurationArgument(Duration::from_secs(5)),
+            transition_from_daytime: DurationArgument(Duration::from_secs(5)),
+            night_from_transition: DurationArgument(Duration::from_secs(10)),
+            transition_from_night: DurationArgument(Duration::from_secs(20)),
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration file, or the defaults if there is none.
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_with(|name| std::env::var_os(name))
+    }
+
+    /// Like [`Config::load`], but with environment variables looked
+    /// up by `var`, such as those of another process.
+    pub fn load_with(var: impl Fn(&str) -> Option<OsString>) -> Result<Self, ConfigError> {
+        match config_path(var) {
+            Some(path) if path.exists() => Self::load_from(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::ReadFailed(path.to_owned(), e))?;
+        let malformed = |mut e: toml::de::Error| {
+            e.set_input(Some(&text));
+            ConfigError::Malformed(path.to_owned(), e)
+        };
+        let mut root = DeTable::parse(&text).map_err(malformed)?;
+        if let Some(dir) = path.parent() {
+            resolve_curve_paths(root.get_mut(), dir);
+        }
+        let config = Self::deserialize(toml::de::Deserializer::from(root)).map_err(malformed)?;
+        config.validate(path)?;
+        Ok(config)
+    }
+
+    /// Check the settings that parse but cannot be used, of the file at `path`.
+    fn validate(&self, path: &Path) -> Result<(), ConfigError> {
+        if self.frequency == Some(0) {
+            return Err(ConfigError::ZeroFrequency(path.to_owned()));
+        }
+        if self.auto.interval.is_zero() {
+            return Err(ConfigError::ZeroInterval(path.to_owned()));
+        }
+        if !(0.0..=1.0).contains(&self.auto.smoothing) {
+            let smoothing = self.auto.smoothing;
+            return Err(ConfigError::SmoothingOutOfRange(path.to_owned(), smoothing));
+        }
+        if let Some((name, _)) = self.devices.iter().find(|(_, device)| device.is_inverted()) {
+            return Err(ConfigError::MinAboveMax(path.to_owned(), name.clone()));
+        }
+        Ok(())
+    }
+
+    /// The device pattern that `pattern` is an alias for, or `pattern` itself.
+    pub fn resolve_alias<'a>(&'a self, pattern: &'a str) -> &'a str {
+        self.aliases.get(pattern).map_or(pattern, String::as_str)
+    }
+
+    /// The settings for a device, preferring an entry for its exact name,
+    /// then the first entry with a matching pattern or alias.
+    pub fn device(&self, detail: &DeviceDetail) -> Option<&DeviceConfig> {
+        self.devices.get(&detail.name).or_else(|| {
+            self.devices
+                .iter()
+                .find(|(pattern, _)| self.matches(pattern, detail))
+                .map(|(_, device)| device)
+        })
+    }
+
+    /// Whether a device pattern or alias matches a device,
+    /// by its path if the pattern contains a `/`, or else by its name.
+    pub fn matches(&self, pattern: &str, detail: &DeviceDetail) -> bool {
+        let pattern = self.resolve_alias(pattern);
+        let Ok(glob) = glob::Pattern::new(pattern) else {
+            return false;
+        };
+        if pattern.contains('/') {
+            glob.matches_path(&detail.path)
+        } else {
+            glob.matches(&detail.name)
+        }
+    }
+
+    /// Find the curve for a device, preferring the last of `curves` with a
+    /// matching device pattern, then the last one without one, then the
+    /// curve configured for the device, then the configured default.
+    pub fn curve_for<'a>(
+        &'a self,
+        curves: &'a [CurveArgument],
+        detail: &DeviceDetail,
+    ) -> &'a Curve {
+        let matching = curves.iter().rev().find(|arg| {
+            arg.device
+                .as_ref()
+                .is_some_and(|pattern| self.matches(pattern, detail))
+        });
+        matching
+            .or_else(|| curves.iter().rev().find(|arg| arg.device.is_none()))
+            .map(|arg| &arg.curve)
+            .or_else(|| self.device(detail)?.curve.as_ref())
+            .or(self.curve.as_ref())
+            .unwrap_or(&Curve::Linear)
+    }
+
+    /// Limit a brightness to the range configured for the device.
+    pub fn clamp(&self, detail: &DeviceDetail, value: u32, max: u32, curve: &Curve) -> u32 {
+        self.device(detail)
+            .map_or(value, |device| device.clamp(value, max, curve))
+    }
+}
+
+impl DeviceConfig {
+    /// Limit a brightness to the range allowed for the device.
+    /// Whether `min` is greater than `max`, as far as can be told without
+    /// the device, since a percentage and an absolute value depend on it.
+    fn is_inverted(&self) -> bool {
+        match (self.min, self.max) {
+            (Some(Value::Percent(min)), Some(Value::Percent(max))) => min > max,
+            (Some(Value::Absolute(min)), Some(Value::Absolute(max))) => min > max,
+            _ => false,
+        }
+    }
+
+    /// Limit `value` to `min` and `max`, where `min` wins if it is greater,
+    /// which [`Config::load_from`] only allows for mixed units.
+    pub fn clamp(&self, value: u32, max: u32, curve: &Curve) -> u32 {
+        let upper = self.max.map_or(max, |limit| limit.to_absolute(max, curve));
+        let lower = self.min.map_or(0, |limit| limit.to_absolute(max, curve));
+        value.min(upper).max(lower)
+    }
+}
+
+/// Make the paths of `file:` curves relative to `dir`, the directory of the
+/// configuration file, instead of the directory that the command runs in.
+fn resolve_curve_paths(config: &mut DeTable<'_>, dir: &Path) {
+    if let Some(curve) = curve_mut(config) {
+        resolve_curve_path(curve, dir);
+    }
+    let devices = config
+        .iter_mut()
+        .find(|(key, _)| key.get_ref() == "devices");
+    if let Some((_, devices)) = devices {
+        if let DeValue::Table(devices) = devices.get_mut() {
+            for (_, device) in devices.iter_mut() {
+                if let DeValue::Table(device) = device.get_mut() {
+                    if let Some(curve) = curve_mut(device) {
+                        resolve_curve_path(curve, dir);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The `curve` setting of a table, if it is a string.
+fn curve_mut<'a, 'i>(table: &'a mut DeTable<'i>) -> Option<&'a mut DeString<'i>> {
+    let (_, curve) = table.iter_mut().find(|(key, _)| key.get_ref() == "curve")?;
+    match curve.get_mut() {
+        DeValue::String(curve) => Some(curve),
+        _ => None,
+    }
+}
+
+fn resolve_curve_path(curve: &mut DeString<'_>, dir: &Path) {
+    if let Some(path) = curve.trim().strip_prefix("file:") {
+        *curve = format!("file:{}", dir.join(path).display()).into();
+    }
+}
+
+/// The path of the configuration file, in `$XDG_CONFIG_HOME` or `~/.config`.
+fn config_path(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join(CONFIG_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use std::path::{Path, PathBuf};
+
+    use test_case::test_case;
+
+    use super::Config;
+    use crate::curve::{Curve, CurveArgument};
+    use crate::discovery::{Capability, DeviceDetail};
+    use crate::value::Value;
+
+    #[test]
+    fn test_gammastep_hook_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [hook.gammastep]
+            daytime = "100%"
+            night = "400"
+            transition-from-night = "1m"
+            "#,
+        )
+        .unwrap();
+        let hook = config.hook.gammastep;
+        assert_eq!(hook.daytime, Value::Percent(1.0));
+        assert_eq!(hook.transition, Value::Percent(0.55));
+        assert_eq!(hook.night, Value::Absolute(400));
+        assert_eq!(*hook.transition_from_night, Duration::from_secs(60));
+        assert_eq!(*hook.daytime_from_transition, Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_device_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [aliases]
+            kbd = "*::kbd_backlight"
+
+            [devices.kbd]
+            max = "50%"
+
+            [devices.intel_backlight]
+            min = "10"
+            max = "900"
+            "#,
+        )
+        .unwrap();
+        let detail = |name: &str| DeviceDetail {
+            name: name.to_owned(),
+            path: PathBuf::from("/sys/class/leds").join(name),
+            capability: Capability::Brightness,
+        };
+
+        let screen = config.device(&detail("intel_backlight")).unwrap();
+        assert_eq!(screen.clamp(0, 1000, &Curve::Linear), 10);
+        assert_eq!(screen.clamp(500, 1000, &Curve::Linear), 500);
+        assert_eq!(screen.clamp(1000, 1000, &Curve::Linear), 900);
+        let kbd = config.device(&detail("tpacpi::kbd_backlight")).unwrap();
+        assert_eq!(kbd.clamp(3, 3, &Curve::Linear), 2);
+        assert!(config.device(&detail("input3::capslock")).is_none());
+    }
+
+    #[test_case(&[], "intel_backlight" => Curve::CieLightness)]
+    #[test_case(&[], "tpacpi::kbd_backlight" => Curve::Gamma(2.0))]
+    #[test_case(&["log", "kbd=linear"], "intel_backlight" => Curve::Logarithmic(100.0))]
+    #[test_case(&["kbd=linear", "gamma"], "tpacpi::kbd_backlight" => Curve::Linear)]
+    fn test_curve_for(curves: &[&str], name: &str) -> Curve {
+        let config: Config = toml::from_str(
+            r#"
+            curve = "cie"
+
+            [aliases]
+            kbd = "*::kbd_backlight"
+
+            [devices.kbd]
+            curve = "gamma:2"
+            "#,
+        )
+        .unwrap();
+        let curves = curves
+            .iter()
+            .map(|curve| curve.parse::<CurveArgument>().unwrap())
+            .collect::<Vec<_>>();
+        let detail = DeviceDetail {
+            name: name.to_owned(),
+            path: PathBuf::from("/sys/class/leds").join(name),
+            capability: Capability::Brightness,
+        };
+        config.curve_for(&curves, &detail).clone()
+    }
+
+    #[test]
+    fn test_auto_config() {
+        let config: Config = toml::from_str(
+            r#"
+            [auto]
+            smoothing = 0.5
+            curve = [[0, "20%"], [100, "60%"]]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.auto.smoothing, 0.5);
+        assert_eq!(*config.auto.interval, Duration::from_secs(1));
+        assert_eq!(config.auto.curve.to_value(50.0).to_string(), "40%");
+        assert!(toml::from_str::<Config>("[auto]\ncurve = [[0, \"20\"]]").is_err());
+    }
+
+    #[test]
+    fn test_malformed_config() {
+        assert!(toml::from_str::<Config>("[hook.gammastep]\nnight = \"200%\"").is_err());
+        assert!(toml::from_str::<Config>("[hook.redshift]").is_err());
+        assert!(toml::from_str::<Config>("curve = \"cubic\"").is_err());
+    }
+
+    #[test]
+    fn test_curve_file_relative_to_config() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("curves")).unwrap();
+        std::fs::write(dir.path().join("curves/panel"), "0% 0\n100% 90").unwrap();
+        let path = dir.path().join("config.toml");
+        let text = "curve = \"file:curves/panel\"\n[devices.kbd]\ncurve = \"file:curves/panel\"";
+        std::fs::write(&path, text).unwrap();
+        let config = Config::load_from(&path).unwrap();
+        let table = "0% 0\n100% 90".parse().unwrap();
+        assert_eq!(config.curve, Some(Curve::Table(table)));
+        assert!(matches!(config.devices["kbd"].curve, Some(Curve::Table(_))));
+    }
+
+    #[test_case("" => None)]
+    #[test_case("frequency = 0" => Some("frequency must be greater than zero".to_owned()))]
+    #[test_case("[auto]\ninterval = \"0s\"" => Some("auto.interval must be greater than zero".to_owned()))]
+    #[test_case("[auto]\nsmoothing = 1.5" => Some("auto.smoothing must be from 0 to 1, not 1.5".to_owned()))]
+    #[test_case("[auto]\nsmoothing = -0.1" => Some("auto.smoothing must be from 0 to 1, not -0.1".to_owned()))]
+    #[test_case("[devices.kbd]\nmin = \"60%\"\nmax = \"50%\"" => Some("min is greater than max for device 'kbd'".to_owned()))]
+    #[test_case("[devices.kbd]\nmin = \"20\"\nmax = \"10\"" => Some("min is greater than max for device 'kbd'".to_owned()))]
+    #[test_case("[devices.kbd]\nmin = \"10\"\nmax = \"50%\"" => None)]
+    fn test_invalid_config(text: &str) -> Option<String> {
+        let config: Config = toml::from_str(text).unwrap();
+        let err = config.validate(Path::new("config.toml")).err()?.to_string();
+        Some(
+            err.trim_start_matches("invalid config file 'config.toml': ")
+                .to_owned(),
+        )
+    }
+}
@@ -0,0 +1,435 @@
+use std::num::{ParseFloatError, ParseIntError};
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+/// The default exponent for [`Curve::Gamma`].
+pub const DEFAULT_GAMMA: f32 = 2.2;
+/// The default base for [`Curve::Logarithmic`].
+pub const DEFAULT_LOG_BASE: f32 = 100.0;
+
+/// A mapping between perceived brightness, expressed as a fraction
+/// of the full range, and the raw brightness value of a device.
+///
+/// The eye's response to luminance is far from linear, so mapping
+/// percentages directly onto `max_brightness` makes low percentages
+/// appear much too bright and high percentages barely distinguishable.
+#[derive(PartialEq, Debug, Clone, Default, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Curve {
+    /// Percentages map directly onto raw values.
+    #[default]
+    Linear,
+    /// Raw values are the perceived fraction raised to the given exponent.
+    Gamma(f32),
+    /// The CIE 1976 lightness (L*) function.
+    CieLightness,
+    /// Raw values grow exponentially with the given base,
+    /// so that perceived brightness grows logarithmically.
+    Logarithmic(f32),
+    /// A user-defined table of points, loaded from a file.
+    Table(CurveTable),
+}
+
+/// A piecewise-linear mapping from perceived fractions to absolute values.
+///
+/// The file format is one point per line, as a percentage followed
+/// by the raw value, separated by whitespace. Empty lines and anything
+/// after a `#` are ignored. The points must cover both `0%` and `100%`,
+/// and both columns must be strictly increasing.
+///
+/// ```text
+/// # percent  raw
+/// 0%         0
+/// 10%        150
+/// 50%        9000
+/// 100%       96000
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+pub struct CurveTable {
+    points: Vec<(f32, u32)>,
+}
+
+/// A [`Curve`] that may be restricted to devices with names
+/// matching a glob pattern, written as `[DEVICE=]CURVE`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CurveArgument {
+    pub device: Option<String>,
+    pub curve: Curve,
+}
+
+#[derive(Clone, Debug, PartialEq, Error)]
+pub enum ParseCurveError {
+    #[error(
+        "unknown curve '{0}', must be one of: `linear`, `gamma[:EXPONENT]`, `cie`, `log[:BASE]`, `file:PATH`"
+    )]
+    UnknownCurve(String),
+    #[error("curve '{0}' does not take a parameter")]
+    UnexpectedParameter(String),
+    #[error("{0} for curve parameter '{1}'")]
+    ParseParameterError(ParseFloatError, String),
+    #[error("gamma exponent '{0}' must be a finite number greater than zero")]
+    GammaOutOfRange(f32),
+    #[error("logarithm base '{0}' must be a finite number greater than one")]
+    LogBaseOutOfRange(f32),
+    #[error("failed to read curve file '{0}': {1}")]
+    ReadTableFailed(String, String),
+    #[error("{1} in curve file '{0}'")]
+    ParseTableError(String, ParseCurveTableError),
+}
+
+#[derive(Clone, Debug, PartialEq, Error)]
+pub enum ParseCurveTableError {
+    #[error("line {0} must have a percentage and a raw value")]
+    MissingColumn(usize),
+    #[error("line {0} has more than two columns")]
+    ExtraColumn(usize),
+    #[error("percentage '{1}' on line {0} is missing a `%` suffix")]
+    MissingPercentSuffix(usize, String),
+    #[error("percentage '{1}' on line {0} must be between 0 and 100")]
+    PercentOutOfRange(usize, f32),
+    #[error("{1} for percentage '{2}' on line {0}")]
+    ParsePercentError(usize, ParseFloatError, String),
+    #[error("{1} for raw value '{2}' on line {0}")]
+    ParseRawError(usize, ParseIntError, String),
+    #[error("point on line {0} does not increase from the previous point")]
+    NotMonotonic(usize),
+    #[error("the points do not cover {0}%")]
+    MissingEndpoint(u8),
+}
+
+impl Curve {
+    /// Convert a perceived fraction to a raw fraction of the full range.
+    pub fn to_raw(&self, perceived: f32) -> f32 {
+        let perceived = perceived.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => perceived,
+            Curve::Gamma(exp) => perceived.powf(*exp),
+            Curve::CieLightness => {
+                if perceived <= 0.08 {
+                    perceived / 9.033
+                } else {
+                    ((perceived + 0.16) / 1.16).powi(3)
+                }
+            }
+            Curve::Logarithmic(base) => (base.powf(perceived) - 1.0) / (base - 1.0),
+            Curve::Table(table) => (table.to_absolute(perceived) / table.full_scale()) as f32,
+        }
+        .clamp(0.0, 1.0)
+    }
+
+    /// Convert a raw fraction of the full range to a perceived fraction.
+    pub fn to_perceived(&self, raw: f32) -> f32 {
+        let raw = raw.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => raw,
+            Curve::Gamma(exp) => raw.powf(exp.recip()),
+            Curve::CieLightness => {
+                if raw <= 0.08 / 9.033 {
+                    raw * 9.033
+                } else {
+                    1.16 * raw.cbrt() - 0.16
+                }
+            }
+            Curve::Logarithmic(base) => (raw * (base - 1.0) + 1.0).ln() / base.ln(),
+            Curve::Table(table) => table.to_percent(raw as f64 * table.full_scale()),
+        }
+        .clamp(0.0, 1.0)
+    }
+
+    /// Convert a perceived fraction to an absolute value no greater than `max`.
+    ///
+    /// Tables define absolute values directly, so `max` only serves to clamp them.
+    pub fn to_absolute(&self, perceived: f32, max: u32) -> u32 {
+        match self {
+            Curve::Table(table) => table.to_absolute(perceived.clamp(0.0, 1.0)).round() as u32,
+            _ => (self.to_raw(perceived) as f64 * max as f64).round() as u32,
+        }
+        .min(max)
+    }
+
+    /// Convert an absolute value to a perceived fraction of `max`.
+    pub fn to_percent(&self, absolute: u32, max: u32) -> f32 {
+        match self {
+            Curve::Table(table) => table.to_percent(absolute.min(max) as f64),
+            _ if max == 0 => 0.0,
+            _ => self.to_perceived((absolute as f64 / max as f64) as f32),
+        }
+    }
+}
+
+impl CurveTable {
+    /// Read and validate a table from the file at `path`.
+    pub fn load(path: &Path) -> Result<Self, ParseCurveError> {
+        let display = path.display().to_string();
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| ParseCurveError::ReadTableFailed(display.clone(), e.to_string()))?;
+        source
+            .parse()
+            .map_err(|e| ParseCurveError::ParseTableError(display, e))
+    }
+
+    /// The absolute value at `100%`.
+    fn full_scale(&self) -> f64 {
+        self.points.last().map_or(0, |&(_, raw)| raw).max(1) as f64
+    }
+
+    fn to_absolute(&self, perceived: f32) -> f64 {
+        let segment = self
+            .points
+            .windows(2)
+            .find(|pair| perceived <= pair[1].0)
+            .unwrap_or(&self.points[self.points.len() - 2..]);
+        let ((p0, r0), (p1, r1)) = (segment[0], segment[1]);
+        let t = ((perceived - p0) / (p1 - p0)).clamp(0.0, 1.0) as f64;
+        r0 as f64 + (r1 as f64 - r0 as f64) * t
+    }
+
+    fn to_percent(&self, absolute: f64) -> f32 {
+        let segment = self
+            .points
+            .windows(2)
+            .find(|pair| absolute <= pair[1].1 as f64)
+            .unwrap_or(&self.points[self.points.len() - 2..]);
+        let ((p0, r0), (p1, r1)) = (segment[0], segment[1]);
+        if r1 == r0 {
+            return p0;
+        }
+        let t = ((absolute - r0 as f64) / (r1 as f64 - r0 as f64)).clamp(0.0, 1.0) as f32;
+        p0 + (p1 - p0) * t
+    }
+}
+
+impl FromStr for CurveTable {
+    type Err = ParseCurveTableError;
+
+    fn from_str(source: &str) -> Result<Self, Self::Err> {
+        use ParseCurveTableError as E;
+
+        let mut points = Vec::<(f32, u32)>::new();
+        for (index, line) in source.lines().enumerate() {
+            let line_num = index + 1;
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut columns = line.split_whitespace();
+            let (Some(percent), Some(raw)) = (columns.next(), columns.next()) else {
+                return Err(E::MissingColumn(line_num));
+            };
+            if columns.next().is_some() {
+                return Err(E::ExtraColumn(line_num));
+            }
+            let percent = percent
+                .strip_suffix('%')
+                .ok_or_else(|| E::MissingPercentSuffix(line_num, percent.to_string()))?
+                .parse::<f32>()
+                .map_err(|e| E::ParsePercentError(line_num, e, percent.to_string()))?;
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(E::PercentOutOfRange(line_num, percent));
+            }
+            let raw = raw
+                .parse::<u32>()
+                .map_err(|e| E::ParseRawError(line_num, e, raw.to_string()))?;
+            let percent = percent / 100.0;
+            if let Some(&(last_percent, last_raw)) = points.last() {
+                if percent <= last_percent || raw <= last_raw {
+                    return Err(E::NotMonotonic(line_num));
+                }
+            }
+            points.push((percent, raw));
+        }
+
+        match (points.first(), points.last()) {
+            (Some(&(first, _)), _) if first != 0.0 => Err(E::MissingEndpoint(0)),
+            (_, Some(&(last, _))) if last != 1.0 => Err(E::MissingEndpoint(100)),
+            (None, _) => Err(E::MissingEndpoint(0)),
+            _ => Ok(Self { points }),
+        }
+    }
+}
+
+impl TryFrom<String> for Curve {
+    type Error = ParseCurveError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl FromStr for Curve {
+    type Err = ParseCurveError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use ParseCurveError as E;
+
+        let value = value.trim();
+        if let Some(path) = value.strip_prefix("file:") {
+            return CurveTable::load(Path::new(path)).map(Curve::Table);
+        }
+        let (name, param) = match value.split_once(':') {
+            Some((name, param)) => (name, Some(param)),
+            None => (value, None),
+        };
+        let param = param
+            .map(|param| {
+                param
+                    .trim()
+                    .parse::<f32>()
+                    .map_err(|e| E::ParseParameterError(e, param.to_string()))
+            })
+            .transpose()?;
+
+        match (name.trim(), param) {
+            ("linear", None) => Ok(Curve::Linear),
+            ("cie", None) => Ok(Curve::CieLightness),
+            ("gamma", exp) => match exp.unwrap_or(DEFAULT_GAMMA) {
+                exp if exp.is_finite() && exp > 0.0 => Ok(Curve::Gamma(exp)),
+                exp => Err(E::GammaOutOfRange(exp)),
+            },
+            ("log", base) => match base.unwrap_or(DEFAULT_LOG_BASE) {
+                base if base.is_finite() && base > 1.0 => Ok(Curve::Logarithmic(base)),
+                base => Err(E::LogBaseOutOfRange(base)),
+            },
+            ("linear" | "cie", Some(_)) => Err(E::UnexpectedParameter(name.to_string())),
+            _ => Err(E::UnknownCurve(name.to_string())),
+        }
+    }
+}
+
+impl FromStr for CurveArgument {
+    type Err = ParseCurveError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once('=') {
+            Some((device, curve)) if !device.contains('/') => Ok(Self {
+                device: Some(device.trim().to_string()),
+                curve: curve.parse()?,
+            }),
+            _ => Ok(Self {
+                device: None,
+                curve: value.parse()?,
+            }),
+        }
+    }
+}
+
+impl std::fmt::Display for Curve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Curve::Linear => write!(f, "linear"),
+            Curve::Gamma(exp) => write!(f, "gamma:{exp}"),
+            Curve::CieLightness => write!(f, "cie"),
+            Curve::Logarithmic(base) => write!(f, "log:{base}"),
+            Curve::Table(table) => {
+                let points = table
+                    .points
+                    .iter()
+                    .map(|(pct, raw)| format!("{}%={raw}", pct * 100.0));
+                write!(f, "table[{}]", points.collect::<Vec<_>>().join(", "))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::{test_case, test_matrix};
+
+    use super::{Curve, CurveTable, ParseCurveError, ParseCurveTableError};
+
+    const TABLE: &str = "
+        # percent  raw
+        0%    0
+        10%   100  # dim
+        50%   2000
+
+        100%  10000
+    ";
+
+    #[test_case("linear" => Curve::Linear)]
+    #[test_case("gamma" => Curve::Gamma(2.2))]
+    #[test_case("gamma:1.8" => Curve::Gamma(1.8))]
+    #[test_case("cie" => Curve::CieLightness)]
+    #[test_case("log" => Curve::Logarithmic(100.0))]
+    #[test_case("log:10" => Curve::Logarithmic(10.0))]
+    fn test_parse_curve(input: &str) -> Curve {
+        input.parse().unwrap()
+    }
+
+    #[test_case("cubic" => ParseCurveError::UnknownCurve("cubic".to_owned()))]
+    #[test_case("cie:2" => ParseCurveError::UnexpectedParameter("cie".to_owned()))]
+    #[test_case("gamma:0" => ParseCurveError::GammaOutOfRange(0.0))]
+    #[test_case("log:1" => ParseCurveError::LogBaseOutOfRange(1.0))]
+    #[test_case("gamma:inf" => ParseCurveError::GammaOutOfRange(f32::INFINITY))]
+    #[test_case("log:inf" => ParseCurveError::LogBaseOutOfRange(f32::INFINITY))]
+    #[test_case("gamma:x" => ParseCurveError::ParseParameterError("x".parse::<f32>().unwrap_err(), "x".to_owned()))]
+    fn test_parse_curve_error(input: &str) -> ParseCurveError {
+        input.parse::<Curve>().unwrap_err()
+    }
+
+    #[test_case("gamma:NaN")]
+    #[test_case("log:NaN")]
+    fn test_parse_curve_nan(input: &str) {
+        let err = input.parse::<Curve>().unwrap_err();
+        assert!(matches!(
+            err,
+            ParseCurveError::GammaOutOfRange(param) | ParseCurveError::LogBaseOutOfRange(param)
+                if param.is_nan()
+        ));
+    }
+
+    #[test_matrix(
+        [Curve::Linear, Curve::Gamma(2.2), Curve::CieLightness, Curve::Logarithmic(100.0)],
+        [0.0, 0.05, 0.25, 0.5, 0.75, 1.0]
+    )]
+    fn test_curve_round_trip(curve: Curve, perceived: f32) {
+        let raw = curve.to_raw(perceived);
+        assert!((0.0..=1.0).contains(&raw));
+        assert!((curve.to_perceived(raw) - perceived).abs() < 1e-4);
+    }
+
+    #[test_matrix([Curve::Gamma(2.2), Curve::CieLightness, Curve::Logarithmic(100.0)])]
+    fn test_curve_darker_than_linear(curve: Curve) {
+        assert!(curve.to_raw(0.1) < 0.1);
+        assert_eq!(curve.to_absolute(1.0, 96000), 96000);
+        assert_eq!(curve.to_absolute(0.0, 96000), 0);
+    }
+
+    #[test_case(0.0 => 0)]
+    #[test_case(0.05 => 50)]
+    #[test_case(0.1 => 100)]
+    #[test_case(0.3 => 1050)]
+    #[test_case(1.0 => 10000)]
+    fn test_table_to_absolute(perceived: f32) -> u32 {
+        let curve = Curve::Table(TABLE.parse().unwrap());
+        curve.to_absolute(perceived, 96000)
+    }
+
+    #[test_case(0 => 0.0)]
+    #[test_case(100 => 0.1)]
+    #[test_case(1050 => 0.3)]
+    #[test_case(10000 => 1.0)]
+    #[test_case(20000 => 1.0)]
+    fn test_table_to_percent(absolute: u32) -> f32 {
+        let curve = Curve::Table(TABLE.parse().unwrap());
+        (curve.to_percent(absolute, 96000) * 100.0).round() / 100.0
+    }
+
+    #[test_case("0% 0\n100%" => ParseCurveTableError::MissingColumn(2))]
+    #[test_case("0% 0 1" => ParseCurveTableError::ExtraColumn(1))]
+    #[test_case("0 0" => ParseCurveTableError::MissingPercentSuffix(1, "0".to_owned()))]
+    #[test_case("0% 0\n101% 5" => ParseCurveTableError::PercentOutOfRange(2, 101.0))]
+    #[test_case("0% -1" => ParseCurveTableError::ParseRawError(1, "-1".parse::<u32>().unwrap_err(), "-1".to_owned()))]
+    #[test_case("0% 0\n50% 10\n40% 20\n100% 30" => ParseCurveTableError::NotMonotonic(3))]
+    #[test_case("0% 10\n50% 5\n100% 30" => ParseCurveTableError::NotMonotonic(2))]
+    #[test_case("0% 0\n50% 10\n60% 10\n100% 30" => ParseCurveTableError::NotMonotonic(3))]
+    #[test_case("10% 0\n100% 30" => ParseCurveTableError::MissingEndpoint(0))]
+    #[test_case("0% 0\n90% 30" => ParseCurveTableError::MissingEndpoint(100))]
+    #[test_case("# empty" => ParseCurveTableError::MissingEndpoint(0))]
+    fn test_parse_table_error(input: &str) -> ParseCurveTableError {
+        input.parse::<CurveTable>().unwrap_err()
+    }
+}
@@ -0,0 +1,242 @@
+use std::ffi::{OsStr, OsString};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::fd::AsRawFd;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+use bpaf::ParseFailure;
+use slight::lock::runtime_dir;
+
+use crate::cli::slight_command;
+use crate::{execute, Context, Environment, Error, Output, Result};
+
+const SOCKET_NAME: &str = "slight.sock";
+const DAEMON_RUNNING: &str = "the daemon is already running";
+const DAEMON_HUNG_UP: &str = "the daemon closed the connection before the command finished";
+
+// Each line of a response from the daemon begins with one of these tags.
+// The fields of a request are each terminated by a null byte, which are the
+// working directory of the client, its environment variables among those
+// that are forwarded, as `NAME=VALUE`, an empty field, and the arguments.
+const TAG_STDOUT: char = 'o';
+const TAG_STDERR: char = 'e';
+/// The final line, followed by an error message if the command failed.
+const TAG_EXIT: char = 'x';
+
+/// The path of the daemon's socket, in the private directory of this user,
+/// so that no other user can connect to it or replace it.
+pub fn socket_path() -> std::io::Result<PathBuf> {
+    Ok(runtime_dir()?.join(SOCKET_NAME))
+}
+
+/// Connect to the daemon, if it is running as this user.
+pub fn connect() -> Option<UnixStream> {
+    let stream = UnixStream::connect(socket_path().ok()?).ok()?;
+    is_same_user(&stream).then_some(stream)
+}
+
+/// Whether the process at the other end of `stream` runs as this user.
+fn is_same_user(stream: &UnixStream) -> bool {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes, and `len` is the size of `cred`.
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            std::ptr::from_mut(&mut cred).cast(),
+            &mut len,
+        )
+    };
+    // SAFETY: `getuid` is always successful.
+    result == 0 && cred.uid == unsafe { libc::getuid() }
+}
+
+/// Send the arguments of this invocation to the daemon, along with its working
+/// directory and environment, so that the command does the same in the daemon.
+pub fn forward(
+    mut stream: UnixStream,
+    env: &Environment,
+    args: impl Iterator<Item = OsString>,
+) -> Result<()> {
+    let cwd = std::env::current_dir().map_err(Error::DaemonSocketFailed)?;
+    let vars = env.0.iter().map(|(name, value)| {
+        let mut var = OsString::from(format!("{name}="));
+        var.push(value);
+        var.into_vec()
+    });
+    let fields = std::iter::once(cwd.into_os_string().into_vec())
+        .chain(vars)
+        .chain(std::iter::once(Vec::new()))
+        .chain(args.map(OsString::into_vec));
+    for field in fields {
+        stream
+            .write_all(&field)
+            .and_then(|_| stream.write_all(b"\0"))
+            .map_err(Error::DaemonSocketFailed)?;
+    }
+    stream
+        .shutdown(Shutdown::Write)
+        .map_err(Error::DaemonSocketFailed)?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line.map_err(Error::DaemonSocketFailed)?;
+        let mut chars = line.chars();
+        match (chars.next(), chars.as_str()) {
+            (Some(TAG_STDOUT), text) => println!("{text}"),
+            (Some(TAG_STDERR), text) => eprintln!("{text}"),
+            (Some(TAG_EXIT), "") => return Ok(()),
+            (Some(TAG_EXIT), message) => return Err(Error::DaemonCommandFailed(message.into())),
+            _ => continue,
+        }
+    }
+    Err(Error::DaemonCommandFailed(DAEMON_HUNG_UP.into()))
+}
+
+/// Listen for commands from other invocations until killed.
+pub fn serve() -> Result<()> {
+    if connect().is_some() {
+        return Err(Error::MalformedArguments(DAEMON_RUNNING.into()));
+    }
+    let path = socket_path().map_err(Error::DaemonSocketFailed)?;
+    // nothing is listening, so the socket is left over from a previous daemon
+    _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).map_err(Error::DaemonSocketFailed)?;
+
+    let ctx = Context::persistent();
+    std::thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                // other users are kept out by the directory, unless its mode was changed
+                Ok(stream) if !is_same_user(&stream) => {}
+                Ok(stream) => {
+                    let ctx = &ctx;
+                    scope.spawn(move || {
+                        if let Err(e) = handle_client(stream, ctx) {
+                            eprintln!("{e}");
+                        }
+                    });
+                }
+                Err(e) => eprintln!("{}", Error::DaemonSocketFailed(e)),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn handle_client(mut stream: UnixStream, ctx: &Context) -> Result<()> {
+    let mut request = Vec::new();
+    stream
+        .read_to_end(&mut request)
+        .map_err(Error::DaemonSocketFailed)?;
+    if request.is_empty() {
+        // only checking if the daemon is running
+        return Ok(());
+    }
+    let mut fields = request
+        .split_inclusive(|&byte| byte == b'\0')
+        .map(|field| OsStr::from_bytes(&field[..field.len() - 1]));
+    let cwd = fields.next().unwrap_or_default();
+    let env = Environment(
+        fields
+            .by_ref()
+            .take_while(|field| !field.is_empty())
+            .filter_map(parse_var)
+            .collect(),
+    );
+    let args = fields.collect::<Vec<_>>();
+
+    let result = enter_dir(Path::new(cwd)).and_then(|()| {
+        match slight_command().run_inner(args.as_slice()) {
+            Ok(args) => {
+                let mut output = Output {
+                    stdout: Box::new(TaggedWriter::new(&stream, TAG_STDOUT)?),
+                    stderr: Box::new(TaggedWriter::new(&stream, TAG_STDERR)?),
+                };
+                execute(args, ctx, &env, &mut output)
+            }
+            Err(ParseFailure::Stderr(doc)) => {
+                Err(Error::MalformedArguments(doc.monochrome(false).into()))
+            }
+            Err(_) => Err(Error::MalformedArguments("unexpected arguments".into())),
+        }
+    });
+
+    let message = match result {
+        Ok(()) => String::new(),
+        Err(e) => e.to_string().replace('\n', " "),
+    };
+    writeln!(stream, "{TAG_EXIT}{message}").map_err(Error::DaemonSocketFailed)
+}
+
+/// Split an environment variable of a request, written as `NAME=VALUE`.
+fn parse_var(var: &OsStr) -> Option<(String, OsString)> {
+    let bytes = var.as_bytes();
+    let split = bytes.iter().position(|&byte| byte == b'=')?;
+    let name = std::str::from_utf8(&bytes[..split]).ok()?;
+    Some((
+        name.to_owned(),
+        OsStr::from_bytes(&bytes[split + 1..]).into(),
+    ))
+}
+
+/// Change the working directory of this thread, and of the threads it spawns,
+/// to that of the client, so that relative paths in its arguments are resolved
+/// as they would be by the client, without affecting any other clients.
+fn enter_dir(dir: &Path) -> Result<()> {
+    // SAFETY: `unshare` has no memory safety requirements.
+    if unsafe { libc::unshare(libc::CLONE_FS) } != 0 {
+        return Err(Error::DaemonSocketFailed(std::io::Error::last_os_error()));
+    }
+    std::env::set_current_dir(dir).map_err(Error::DaemonSocketFailed)
+}
+
+/// Writes each complete line to the client, prefixed with a tag.
+struct TaggedWriter {
+    stream: UnixStream,
+    tag: char,
+    buf: Vec<u8>,
+}
+
+impl TaggedWriter {
+    fn new(stream: &UnixStream, tag: char) -> Result<Self> {
+        Ok(Self {
+            stream: stream.try_clone().map_err(Error::DaemonSocketFailed)?,
+            tag,
+            buf: Vec::new(),
+        })
+    }
+}
+
+impl Write for TaggedWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        while let Some(end) = self.buf.iter().position(|&byte| byte == b'\n') {
+            let line = self.buf.drain(..=end).collect::<Vec<_>>();
+            write!(self.stream, "{}", self.tag)?;
+            self.stream.write_all(&line)?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buf.is_empty() {
+            self.buf.push(b'\n');
+            self.write(&[])?;
+        }
+        self.stream.flush()
+    }
+}
+
+impl Drop for TaggedWriter {
+    fn drop(&mut self) {
+        _ = self.flush();
+    }
+}
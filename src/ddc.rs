@@ -0,0 +1,294 @@
+use std::cell::RefCell;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::time::Duration;
+
+use once_cell::unsync::OnceCell;
+
+use crate::device::{Brightness, ReadNumResult, WriteResult};
+
+/// The I2C slave address of the DDC/CI command interface.
+const DDC_CI_ADDRESS: u8 = 0x37;
+/// The address the host writes to, used when computing checksums.
+const HOST_WRITE_ADDRESS: u8 = 0x6E;
+/// The source address the host uses when sending commands.
+const HOST_SOURCE_ADDRESS: u8 = 0x51;
+/// The virtual host address used when computing reply checksums.
+const HOST_REPLY_ADDRESS: u8 = 0x50;
+
+const OPCODE_GET_VCP: u8 = 0x01;
+const OPCODE_GET_VCP_REPLY: u8 = 0x02;
+const OPCODE_SET_VCP: u8 = 0x03;
+
+/// The MCCS VCP feature code for luminance.
+pub const VCP_BRIGHTNESS: u8 = 0x10;
+
+/// The time the display needs before a reply can be read.
+const REPLY_DELAY: Duration = Duration::from_millis(40);
+/// The time the display needs after setting a value.
+const SET_DELAY: Duration = Duration::from_millis(50);
+
+/// From `linux/i2c-dev.h`.
+const I2C_SLAVE: libc::c_ulong = 0x0703;
+
+/// A raw byte transport to the DDC/CI address of a display.
+///
+/// This is implemented by [`I2cDevice`] for `/dev/i2c-*` character devices,
+/// and can be implemented by in-process mocks for testing.
+pub trait I2cTransport {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<()>;
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<()>;
+}
+
+/// An I2C bus from the `i2c-dev` kernel module, bound to the DDC/CI address.
+#[derive(Debug)]
+pub struct I2cDevice {
+    file: File,
+}
+
+impl I2cDevice {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // SAFETY: the file descriptor is valid for the lifetime of `file`,
+        // and `I2C_SLAVE` takes the address as an integer argument.
+        let res = unsafe {
+            libc::ioctl(
+                file.as_raw_fd(),
+                I2C_SLAVE as _,
+                DDC_CI_ADDRESS as libc::c_ulong,
+            )
+        };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self { file })
+    }
+}
+
+impl I2cTransport for I2cDevice {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(data)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        self.file.read_exact(buf)
+    }
+}
+
+/// A display controlled with the VCP brightness feature over DDC/CI.
+///
+/// <https://milek7.pl/ddcbacklight/mccs.pdf>
+#[derive(Debug)]
+pub struct DdcDevice<T: I2cTransport = I2cDevice> {
+    transport: RefCell<T>,
+    delay: bool,
+    max_brightness: OnceCell<u32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VcpValue {
+    pub current: u16,
+    pub max: u16,
+}
+
+impl DdcDevice<I2cDevice> {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            transport: RefCell::new(I2cDevice::open(path)?),
+            delay: true,
+            max_brightness: OnceCell::new(),
+        })
+    }
+}
+
+impl<T: I2cTransport> DdcDevice<T> {
+    /// Use an arbitrary transport, without waiting for the display between commands.
+    pub fn with_transport(transport: T) -> Self {
+        Self {
+            transport: RefCell::new(transport),
+            delay: false,
+            max_brightness: OnceCell::new(),
+        }
+    }
+
+    fn sleep(&self, duration: Duration) {
+        if self.delay {
+            std::thread::sleep(duration);
+        }
+    }
+
+    fn send(&self, payload: &[u8]) -> std::io::Result<()> {
+        let mut message = Vec::with_capacity(payload.len() + 3);
+        message.push(HOST_SOURCE_ADDRESS);
+        message.push(0x80 | payload.len() as u8);
+        message.extend_from_slice(payload);
+        message.push(checksum(HOST_WRITE_ADDRESS, &message));
+        self.transport.borrow_mut().write(&message)
+    }
+
+    pub fn get_vcp(&self, feature: u8) -> std::io::Result<VcpValue> {
+        self.send(&[OPCODE_GET_VCP, feature])?;
+        self.sleep(REPLY_DELAY);
+        let mut reply = [0_u8; 11];
+        self.transport.borrow_mut().read(&mut reply)?;
+
+        let (body, [expected]) = reply.split_at(reply.len() - 1) else {
+            unreachable!()
+        };
+        if checksum(HOST_REPLY_ADDRESS, body) != *expected {
+            return Err(invalid_data("DDC/CI reply checksum mismatch"));
+        }
+        match reply {
+            [_, 0x88, OPCODE_GET_VCP_REPLY, 0x00, code, _, max_hi, max_lo, cur_hi, cur_lo, _]
+                if code == feature =>
+            {
+                Ok(VcpValue {
+                    current: u16::from_be_bytes([cur_hi, cur_lo]),
+                    max: u16::from_be_bytes([max_hi, max_lo]),
+                })
+            }
+            [_, 0x88, OPCODE_GET_VCP_REPLY, 0x01, ..] => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("display does not support VCP feature 0x{feature:02x}"),
+            )),
+            _ => Err(invalid_data("malformed DDC/CI reply")),
+        }
+    }
+
+    pub fn set_vcp(&self, feature: u8, value: u16) -> std::io::Result<()> {
+        let [hi, lo] = value.to_be_bytes();
+        self.send(&[OPCODE_SET_VCP, feature, hi, lo])?;
+        self.sleep(SET_DELAY);
+        Ok(())
+    }
+}
+
+impl<T: I2cTransport> Brightness for DdcDevice<T> {
+    fn brightness(&self) -> ReadNumResult<u32> {
+        let value = self.get_vcp(VCP_BRIGHTNESS)?;
+        _ = self.max_brightness.set(value.max as u32);
+        Ok(value.current as u32)
+    }
+
+    fn set_brightness(&self, value: u32) -> WriteResult {
+        self.set_vcp(VCP_BRIGHTNESS, value.min(u16::MAX as u32) as u16)
+    }
+
+    fn max_brightness(&self) -> ReadNumResult<u32> {
+        self.max_brightness
+            .get_or_try_init(|| Ok(self.get_vcp(VCP_BRIGHTNESS)?.max as u32))
+            .copied()
+    }
+
+    fn is_slow_to_read(&self) -> bool {
+        true
+    }
+}
+
+fn checksum(initial: u8, bytes: &[u8]) -> u8 {
+    bytes.iter().fold(initial, |acc, byte| acc ^ byte)
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::{checksum, DdcDevice, I2cTransport, HOST_REPLY_ADDRESS, HOST_WRITE_ADDRESS};
+    use crate::device::Brightness;
+
+    /// A display that answers VCP requests for a single feature.
+    #[derive(Default)]
+    struct MockMonitor {
+        current: u16,
+        max: u16,
+        supported: bool,
+        corrupt: bool,
+        written: Vec<Vec<u8>>,
+        replies: VecDeque<[u8; 11]>,
+    }
+
+    impl I2cTransport for MockMonitor {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+            let (body, [sum]) = data.split_at(data.len() - 1) else {
+                unreachable!()
+            };
+            assert_eq!(checksum(HOST_WRITE_ADDRESS, body), *sum);
+            match body {
+                [0x51, 0x82, 0x01, code] => {
+                    let [max_hi, max_lo] = self.max.to_be_bytes();
+                    let [cur_hi, cur_lo] = self.current.to_be_bytes();
+                    let result = if self.supported { 0x00 } else { 0x01 };
+                    let mut reply = [
+                        0x6E, 0x88, 0x02, result, *code, 0x00, max_hi, max_lo, cur_hi, cur_lo, 0,
+                    ];
+                    reply[10] = checksum(HOST_REPLY_ADDRESS, &reply[..10]) ^ self.corrupt as u8;
+                    self.replies.push_back(reply);
+                }
+                [0x51, 0x84, 0x03, 0x10, hi, lo] => {
+                    self.current = u16::from_be_bytes([*hi, *lo]);
+                }
+                _ => panic!("unexpected message: {data:02x?}"),
+            }
+            self.written.push(data.to_vec());
+            Ok(())
+        }
+
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+            buf.copy_from_slice(&self.replies.pop_front().expect("no reply pending"));
+            Ok(())
+        }
+    }
+
+    fn monitor(current: u16, max: u16) -> DdcDevice<MockMonitor> {
+        DdcDevice::with_transport(MockMonitor {
+            current,
+            max,
+            supported: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_ddc_get_brightness() {
+        let device = monitor(40, 100);
+        assert_eq!(device.brightness().unwrap(), 40);
+        assert_eq!(device.max_brightness().unwrap(), 100);
+        assert_eq!(
+            device.transport.borrow().written[0],
+            [0x51, 0x82, 0x01, 0x10, 0xAC]
+        );
+    }
+
+    #[test]
+    fn test_ddc_set_brightness() {
+        let device = monitor(40, 100);
+        device.set_brightness(75).unwrap();
+        assert_eq!(device.brightness().unwrap(), 75);
+        assert_eq!(
+            device.transport.borrow().written[0],
+            [0x51, 0x84, 0x03, 0x10, 0x00, 0x4B, 0xE3]
+        );
+    }
+
+    #[test]
+    fn test_ddc_checksum_mismatch() {
+        let device = monitor(40, 100);
+        device.transport.borrow_mut().corrupt = true;
+        let err = device.get_vcp(0x10).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_ddc_unsupported_feature() {
+        let device = monitor(40, 100);
+        device.transport.borrow_mut().supported = false;
+        let err = device.get_vcp(0x10).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    }
+}
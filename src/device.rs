@@ -1,9 +1,12 @@
+use std::convert::Infallible;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, Write};
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use derive_more::Display;
 use once_cell::unsync::OnceCell;
+use serde::{Deserialize, Serialize};
 use strum::EnumString;
 use thiserror::Error;
 
@@ -16,14 +19,42 @@ pub enum ReadNumError {
 pub type ReadNumResult<T> = Result<T, ReadNumError>;
 pub type WriteResult = std::io::Result<()>;
 
-#[derive(PartialEq, Debug, Clone, Copy)]
+/// The `FB_BLANK_*` values of `bl_power`, from `include/uapi/linux/fb.h`.
+/// Only [`PowerState::Unblank`] means that the backlight is on.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
 pub enum PowerState {
     Unblank = 0,
+    Normal = 1,
+    VsyncSuspend = 2,
+    HsyncSuspend = 3,
     Powerdown = 4,
 }
 
-#[derive(EnumString, PartialEq, Debug, Clone, Copy)]
+impl PowerState {
+    pub fn is_on(self) -> bool {
+        self == PowerState::Unblank
+    }
+}
+
+impl TryFrom<u8> for PowerState {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
+        match value {
+            0 => Ok(PowerState::Unblank),
+            1 => Ok(PowerState::Normal),
+            2 => Ok(PowerState::VsyncSuspend),
+            3 => Ok(PowerState::HsyncSuspend),
+            4 => Ok(PowerState::Powerdown),
+            value => Err(value),
+        }
+    }
+}
+
+#[derive(EnumString, Serialize, PartialEq, Debug, Clone, Copy)]
 #[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
 pub enum DeviceType {
     Firmware,
     Platform,
@@ -33,14 +64,23 @@ pub enum DeviceType {
 /// This API corresponds to the basics that `sysfs-class-led` and
 /// `sysfs-class-backlight` have in common.
 ///
-/// TODO: missing `trigger`.
-///
-/// There is more to LED devices that is not specified by this trait.
-/// <https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-class-led>
+/// The rest of the API of LED devices is specified by [`Led`].
 pub trait Brightness {
     fn brightness(&self) -> ReadNumResult<u32>;
     fn set_brightness(&self, value: u32) -> WriteResult;
     fn max_brightness(&self) -> ReadNumResult<u32>;
+
+    /// The brightness last set by the hardware, such as by a brightness key,
+    /// if the device supports reporting it and it has happened yet.
+    fn brightness_hw_changed(&self) -> Option<u32> {
+        None
+    }
+
+    /// Whether reading the brightness takes long, such as over DDC/CI,
+    /// so that it should not be read back after every write.
+    fn is_slow_to_read(&self) -> bool {
+        false
+    }
 }
 
 /// This API corresponds to:
@@ -52,6 +92,95 @@ pub trait Backlight: Brightness {
     fn device_type(&self) -> std::io::Result<DeviceType>;
 }
 
+/// This API corresponds to the triggers of:
+/// <https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-class-led>
+///
+/// The attributes of a trigger only exist while it is the current trigger.
+pub trait Led: Brightness {
+    fn triggers(&self) -> std::io::Result<Triggers>;
+    fn set_trigger(&self, trigger: &str) -> WriteResult;
+    /// <https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-class-led-trigger-timer>
+    fn timer(&self) -> ReadNumResult<TimerTrigger>;
+    fn set_timer(&self, timer: TimerTrigger) -> WriteResult;
+    /// <https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-class-led-trigger-netdev>
+    fn netdev(&self) -> ReadNumResult<NetdevTrigger>;
+    fn set_netdev(&self, netdev: &NetdevTrigger) -> WriteResult;
+    /// <https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-class-led-trigger-pattern>
+    ///
+    /// Whether the LED can run a pattern in hardware, with the `pattern` trigger.
+    fn has_hw_pattern(&self) -> bool;
+    fn set_hw_pattern(&self, pattern: &PatternTrigger) -> WriteResult;
+    /// Run a pattern with the `pattern` trigger, timed by the kernel in software.
+    fn set_pattern(&self, pattern: &PatternTrigger) -> WriteResult;
+}
+
+/// This API corresponds to:
+/// <https://www.kernel.org/doc/Documentation/ABI/testing/sysfs-class-led-multicolor>
+///
+/// The brightness of each channel is its intensity scaled
+/// by the overall brightness of the LED.
+pub trait Multicolor: Led {
+    /// The names of the channels, such as `red green blue`.
+    fn multi_index(&self) -> std::io::Result<Vec<String>>;
+    /// The intensity of each channel, in the order of [`Multicolor::multi_index`].
+    fn multi_intensity(&self) -> ReadNumResult<Vec<u32>>;
+    fn set_multi_intensity(&self, intensity: &[u32]) -> WriteResult;
+}
+
+/// The triggers an LED supports, listed by its `trigger` attribute,
+/// where the current trigger is in brackets: `none [timer] heartbeat`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Triggers {
+    pub available: Vec<String>,
+    pub current: Option<String>,
+}
+
+impl FromStr for Triggers {
+    type Err = Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut current = None;
+        let available = value
+            .split_whitespace()
+            .map(|trigger| match trigger.strip_prefix('[') {
+                Some(trigger) => {
+                    let trigger = trigger.strip_suffix(']').unwrap_or(trigger).to_owned();
+                    current = Some(trigger.clone());
+                    trigger
+                }
+                None => trigger.to_owned(),
+            })
+            .collect();
+        Ok(Self { available, current })
+    }
+}
+
+/// The attributes of the `timer` trigger, in milliseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TimerTrigger {
+    pub delay_on: u32,
+    pub delay_off: u32,
+}
+
+/// The attributes of the `netdev` trigger: the network interface,
+/// and whether the LED shows its link state, and transmissions.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NetdevTrigger {
+    pub device_name: String,
+    pub link: bool,
+    pub rx: bool,
+    pub tx: bool,
+}
+
+/// The attributes of the `pattern` trigger: pairs of brightness and
+/// milliseconds, over which the brightness changes to that of the next
+/// pair, and the number of times to run the pattern, or `-1` for forever.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PatternTrigger {
+    pub pattern: Vec<(u32, u32)>,
+    pub repeat: i32,
+}
+
 #[derive(Debug)]
 pub struct LedDevice {
     path: PathBuf,
@@ -79,6 +208,103 @@ impl LedDevice {
     }
 }
 
+impl LedDevice {
+    fn read_attribute(&self, name: &str) -> std::io::Result<String> {
+        let buf = std::fs::read_to_string(self.path.join(name))?;
+        Ok(buf.trim().to_owned())
+    }
+
+    fn read_flag(&self, name: &str) -> ReadNumResult<bool> {
+        Ok(self.read_attribute(name)?.parse::<u8>()? != 0)
+    }
+
+    fn write_attribute(&self, name: &str, value: impl std::fmt::Display) -> WriteResult {
+        std::fs::write(self.path.join(name), value.to_string())
+    }
+
+    fn write_pattern(&self, attribute: &str, pattern: &PatternTrigger) -> WriteResult {
+        let pairs = pattern
+            .pattern
+            .iter()
+            .map(|(brightness, ms)| format!("{brightness} {ms}"))
+            .collect::<Vec<_>>();
+        // the pattern starts over when either is written, so the repeat goes first
+        self.write_attribute("repeat", pattern.repeat)?;
+        self.write_attribute(attribute, pairs.join(" "))
+    }
+}
+
+impl Led for LedDevice {
+    fn triggers(&self) -> std::io::Result<Triggers> {
+        let Ok(triggers) = self.read_attribute("trigger")?.parse();
+        Ok(triggers)
+    }
+
+    fn set_trigger(&self, trigger: &str) -> WriteResult {
+        self.write_attribute("trigger", trigger)
+    }
+
+    fn timer(&self) -> ReadNumResult<TimerTrigger> {
+        Ok(TimerTrigger {
+            delay_on: self.read_attribute("delay_on")?.parse()?,
+            delay_off: self.read_attribute("delay_off")?.parse()?,
+        })
+    }
+
+    fn set_timer(&self, timer: TimerTrigger) -> WriteResult {
+        self.write_attribute("delay_on", timer.delay_on)?;
+        self.write_attribute("delay_off", timer.delay_off)
+    }
+
+    fn netdev(&self) -> ReadNumResult<NetdevTrigger> {
+        Ok(NetdevTrigger {
+            device_name: self.read_attribute("device_name")?,
+            link: self.read_flag("link")?,
+            rx: self.read_flag("rx")?,
+            tx: self.read_flag("tx")?,
+        })
+    }
+
+    fn set_netdev(&self, netdev: &NetdevTrigger) -> WriteResult {
+        self.write_attribute("device_name", &netdev.device_name)?;
+        self.write_attribute("link", netdev.link as u8)?;
+        self.write_attribute("rx", netdev.rx as u8)?;
+        self.write_attribute("tx", netdev.tx as u8)
+    }
+
+    fn has_hw_pattern(&self) -> bool {
+        self.path.join("hw_pattern").exists()
+    }
+
+    fn set_hw_pattern(&self, pattern: &PatternTrigger) -> WriteResult {
+        self.write_pattern("hw_pattern", pattern)
+    }
+
+    fn set_pattern(&self, pattern: &PatternTrigger) -> WriteResult {
+        self.write_pattern("pattern", pattern)
+    }
+}
+
+impl Multicolor for LedDevice {
+    fn multi_index(&self) -> std::io::Result<Vec<String>> {
+        let index = self.read_attribute("multi_index")?;
+        Ok(index.split_whitespace().map(str::to_owned).collect())
+    }
+
+    fn multi_intensity(&self) -> ReadNumResult<Vec<u32>> {
+        let intensity = self.read_attribute("multi_intensity")?;
+        Ok(intensity
+            .split_whitespace()
+            .map(str::parse)
+            .collect::<Result<_, _>>()?)
+    }
+
+    fn set_multi_intensity(&self, intensity: &[u32]) -> WriteResult {
+        let intensity = intensity.iter().map(u32::to_string).collect::<Vec<_>>();
+        self.write_attribute("multi_intensity", intensity.join(" "))
+    }
+}
+
 impl BacklightDevice {
     pub fn new(path: PathBuf) -> Self {
         Self {
@@ -107,7 +333,15 @@ macro_rules! impl_brightness {
     ($struct:path) => {
         impl Brightness for $struct {
             fn brightness(&self) -> ReadNumResult<u32> {
-                let mut file = device_file!(self, file_brightness, "brightness", true)?;
+                let mut file = match device_file!(self, file_brightness, "brightness", true) {
+                    Ok(file) => file,
+                    // without write permissions, the brightness can still be read
+                    Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+                        let buf = std::fs::read_to_string(self.path.join("brightness"))?;
+                        return Ok(buf.trim().parse()?);
+                    }
+                    Err(e) => return Err(e.into()),
+                };
                 let mut buf = String::new();
                 file.read_to_string(&mut buf)?;
                 file.rewind()?;
@@ -131,6 +365,11 @@ macro_rules! impl_brightness {
                     })
                     .copied()
             }
+
+            fn brightness_hw_changed(&self) -> Option<u32> {
+                let buf = std::fs::read_to_string(self.path.join("brightness_hw_changed")).ok()?;
+                buf.trim().parse().ok()
+            }
         }
     };
 }
@@ -140,14 +379,13 @@ impl_brightness!(BacklightDevice);
 
 impl Backlight for BacklightDevice {
     fn bl_power(&self) -> std::io::Result<PowerState> {
-        let mut file = device_file!(self, file_bl_power, "bl_power", true)?;
-        let mut buf = [0_u8];
-        file.read_exact(&mut buf)?;
-        match &buf {
-            b"0" => Ok(PowerState::Unblank),
-            b"4" => Ok(PowerState::Powerdown),
-            _ => unreachable!(),
-        }
+        let buf = std::fs::read_to_string(self.path.join("bl_power"))?;
+        let invalid = || {
+            let message = format!("unknown power state '{}'", buf.trim());
+            std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+        };
+        let value = buf.trim().parse::<u8>().map_err(|_| invalid())?;
+        PowerState::try_from(value).map_err(|_| invalid())
     }
 
     fn set_bl_power(&self, value: PowerState) -> WriteResult {
@@ -158,7 +396,7 @@ impl Backlight for BacklightDevice {
     }
 
     fn actual_brightness(&self) -> ReadNumResult<u32> {
-        let mut file = device_file!(self, file_actual_brightness, "brightness", false)?;
+        let mut file = device_file!(self, file_actual_brightness, "actual_brightness", false)?;
         let mut buf = String::new();
         file.read_to_string(&mut buf)?;
         file.rewind()?;
@@ -171,8 +409,75 @@ impl Backlight for BacklightDevice {
                 let mut file = File::open(self.path.join("type"))?;
                 let mut buf = String::new();
                 file.read_to_string(&mut buf)?;
-                Ok(buf.trim().parse().unwrap())
+                buf.trim()
+                    .parse()
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
             })
             .copied()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use super::{
+        Backlight, BacklightDevice, Led, LedDevice, NetdevTrigger, PowerState, TimerTrigger,
+        Triggers,
+    };
+
+    #[test_case("0\n" => Some(PowerState::Unblank))]
+    #[test_case("1\n" => Some(PowerState::Normal))]
+    #[test_case("2\n" => Some(PowerState::VsyncSuspend))]
+    #[test_case("3\n" => Some(PowerState::HsyncSuspend))]
+    #[test_case("4\n" => Some(PowerState::Powerdown))]
+    #[test_case("5\n" => None)]
+    #[test_case("on\n" => None)]
+    fn test_bl_power(contents: &str) -> Option<PowerState> {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("bl_power"), contents).unwrap();
+        BacklightDevice::new(dir.path().to_owned()).bl_power().ok()
+    }
+
+    #[test_case("none [timer] heartbeat\n", &["none", "timer", "heartbeat"], Some("timer"))]
+    #[test_case("[none] kbd-capslock", &["none", "kbd-capslock"], Some("none"))]
+    #[test_case("none netdev", &["none", "netdev"], None)]
+    fn test_parse_triggers(value: &str, available: &[&str], current: Option<&str>) {
+        let triggers = value.parse::<Triggers>().unwrap();
+        assert_eq!(triggers.available, available);
+        assert_eq!(triggers.current.as_deref(), current);
+    }
+
+    #[test]
+    fn test_trigger_attributes() {
+        let dir = tempfile::tempdir().unwrap();
+        let led = LedDevice::new(dir.path().to_owned());
+        std::fs::write(dir.path().join("trigger"), "none [timer] netdev\n").unwrap();
+        std::fs::write(dir.path().join("delay_on"), "500\n").unwrap();
+        std::fs::write(dir.path().join("delay_off"), "500\n").unwrap();
+        assert_eq!(led.triggers().unwrap().current.as_deref(), Some("timer"));
+        assert_eq!(
+            led.timer().unwrap(),
+            TimerTrigger {
+                delay_on: 500,
+                delay_off: 500
+            }
+        );
+        led.set_timer(TimerTrigger {
+            delay_on: 100,
+            delay_off: 900,
+        })
+        .unwrap();
+        assert_eq!(led.timer().unwrap().delay_off, 900);
+
+        let netdev = NetdevTrigger {
+            device_name: "wlan0".to_owned(),
+            link: true,
+            rx: false,
+            tx: true,
+        };
+        led.set_netdev(&netdev).unwrap();
+        assert_eq!(led.netdev().unwrap(), netdev);
+        assert_eq!(std::fs::read_to_string(dir.path().join("tx")).unwrap(), "1");
+    }
+}
@@ -1,15 +1,110 @@
+use std::os::unix::fs::FileTypeExt;
 use std::path::{Path, PathBuf};
 
+use once_cell::unsync::OnceCell;
+use serde::Serialize;
 use strum::Display;
 use thiserror::Error;
 
+use crate::config::Config;
+use crate::device::{DeviceType, LedDevice};
+
 const BRIGHTNESS_CAPABILITY_FILES: &[&str; 2] = &["brightness", "max_brightness"];
 const BACKLIGHT_CAPABILITY_FILES: &[&str; 3] = &["actual_brightness", "bl_power", "type"];
+const MULTICOLOR_CAPABILITY_FILES: &[&str; 2] = &["multi_index", "multi_intensity"];
+
+/// The environment variable that overrides the root of sysfs.
+pub const SYSFS_ROOT_ENV: &str = "SLIGHT_SYSFS_ROOT";
+const DEFAULT_SYSFS_ROOT: &str = "/sys";
+
+/// The classes of devices that are found by default, within sysfs.
+const DEVICE_CLASSES: &[&str; 2] = &["class/backlight", "class/leds"];
+const DRM_CLASS: &str = "class/drm";
+const I2C_DEV_PATH: &str = "/dev";
+/// Connector types of internal panels, which use a backlight device instead of DDC/CI.
+const INTERNAL_CONNECTOR_TYPES: &[&str; 3] = &["eDP", "LVDS", "DSI"];
+
+/// The root of sysfs, which is `/sys` unless the devices are
+/// somewhere else, as in tests, containers, and chroots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sysfs {
+    root: PathBuf,
+}
+
+impl Default for Sysfs {
+    fn default() -> Self {
+        Self::new(DEFAULT_SYSFS_ROOT)
+    }
+}
+
+impl Sysfs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The path of `path` within this sysfs, where `path` is either
+    /// relative to the root, or an absolute path within `/sys`.
+    /// Any other absolute path is returned as it is.
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        let path = path.as_ref();
+        self.root
+            .join(path.strip_prefix(DEFAULT_SYSFS_ROOT).unwrap_or(path))
+    }
+
+    /// Like [`Sysfs::join`], but for a glob pattern,
+    /// where the root is escaped so that it only matches itself.
+    pub fn join_pattern(&self, pattern: &str) -> String {
+        match pattern.strip_prefix(DEFAULT_SYSFS_ROOT) {
+            Some(rest) if rest.starts_with('/') => {
+                let root = glob::Pattern::escape(&self.root.to_string_lossy());
+                format!("{}{rest}", root.trim_end_matches('/'))
+            }
+            _ => pattern.to_owned(),
+        }
+    }
+}
+
+/// The devices of a sysfs tree, which are only looked for when first used.
+#[derive(Debug, Default)]
+pub struct FoundDevices {
+    sysfs: Sysfs,
+    devices: OnceCell<Vec<DeviceDetail>>,
+}
+
+impl FoundDevices {
+    pub fn new(sysfs: Sysfs) -> Self {
+        Self {
+            sysfs,
+            devices: OnceCell::new(),
+        }
+    }
+
+    pub fn sysfs(&self) -> &Sysfs {
+        &self.sysfs
+    }
+}
+
+impl std::ops::Deref for FoundDevices {
+    type Target = [DeviceDetail];
+
+    fn deref(&self) -> &Self::Target {
+        self.devices.get_or_init(|| find_devices(&self.sysfs))
+    }
+}
 
-#[derive(Debug, Display, Clone, Copy)]
+#[derive(Debug, Display, Serialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
 pub enum Capability {
     Brightness,
     Backlight,
+    /// An LED with several colors, which are mixed by the intensity of each.
+    Multicolor,
+    /// An I2C character device, which may be a display supporting DDC/CI.
+    DdcCi,
     /// No errors occurred, but the specified path does not look
     /// like either a brightness or backlight device.
     None,
@@ -47,6 +142,10 @@ impl CapabilityCheckError {
 
 impl Capability {
     pub fn check(path: &Path) -> Result<Self, CapabilityCheckError> {
+        // I2C buses are character devices, not directories
+        if is_i2c_device(path) {
+            return Ok(Capability::DdcCi);
+        }
         // do checks on the path to make sure all further errors are
         // truly unexpected, bubble error
         match path.try_exists() {
@@ -76,7 +175,7 @@ impl Capability {
                         whilst: format!("iterating device path: {}", path.display()),
                     })?;
                     // filter entries to those who are regular files with reasonable names
-                    if entry.file_type().map_or(false, |f| f.is_file()) {
+                    if entry.file_type().is_ok_and(|f| f.is_file()) {
                         if let Ok(f) = entry.file_name().into_string() {
                             files.push(f);
                         }
@@ -97,9 +196,16 @@ impl Capability {
                 .map(|a| files.iter().any(|b| a == b))
                 .into_iter()
                 .all(|x| x);
+        let has_multicolor = has_brightness
+            && MULTICOLOR_CAPABILITY_FILES
+                .map(|a| files.iter().any(|b| a == b))
+                .into_iter()
+                .all(|x| x);
         // return the discovered capability
         Ok(if has_backlight {
             Capability::Backlight
+        } else if has_multicolor {
+            Capability::Multicolor
         } else if has_brightness {
             Capability::Brightness
         } else {
@@ -134,3 +240,307 @@ impl TryFrom<PathBuf> for DeviceDetail {
         }
     }
 }
+
+impl DeviceDetail {
+    /// How preferable the device is as the default, where lower is better.
+    ///
+    /// Backlights come first, then external displays, then anything else.
+    /// Backlights are ordered by type as the kernel recommends: firmware,
+    /// platform, raw, and then devices of disabled display connectors are
+    /// avoided. Ties are broken by name so that the choice does not depend
+    /// on the order of directory entries.
+    ///
+    /// This reads sysfs, so it is best computed once per device.
+    pub fn default_priority(&self) -> (u8, u8, bool, &str) {
+        let capability = match self.capability {
+            Capability::Backlight => 0,
+            Capability::DdcCi => 1,
+            Capability::Brightness | Capability::Multicolor => 2,
+            Capability::None => 3,
+        };
+        let device_type = std::fs::read_to_string(self.path.join("type"))
+            .ok()
+            .and_then(|kind| kind.trim().parse().ok());
+        let device_type = match device_type {
+            Some(DeviceType::Firmware) => 0,
+            Some(DeviceType::Platform) => 1,
+            Some(DeviceType::Raw) => 2,
+            None => 3,
+        };
+        (
+            capability,
+            device_type,
+            self.connector_disabled(),
+            &self.name,
+        )
+    }
+
+    /// Whether the device belongs to a DRM connector that is disabled,
+    /// such as the panel of a GPU that is not driving the display.
+    fn connector_disabled(&self) -> bool {
+        std::fs::read_to_string(self.path.join("device/enabled"))
+            .is_ok_and(|enabled| enabled.trim() == "disabled")
+    }
+}
+
+fn is_i2c_device(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("i2c-"))
+        && path
+            .metadata()
+            .is_ok_and(|meta| meta.file_type().is_char_device())
+}
+
+/// Find the I2C buses of connected external displays, which may support DDC/CI.
+///
+/// The devices are named after their DRM connectors, such as `card0-DP-1`.
+/// This requires the `i2c-dev` kernel module to be loaded.
+pub fn find_ddc_devices(sysfs: &Sysfs) -> Vec<DeviceDetail> {
+    let Ok(entries) = sysfs.join(DRM_CLASS).read_dir() else {
+        return Vec::new();
+    };
+    let mut devices = entries
+        .filter_map(|res| res.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let connector = name.split_once('-')?.1;
+            if INTERNAL_CONNECTOR_TYPES
+                .iter()
+                .any(|kind| connector.starts_with(kind))
+            {
+                return None;
+            }
+            let status = std::fs::read_to_string(entry.path().join("status")).ok()?;
+            if status.trim() != "connected" {
+                return None;
+            }
+            let bus = entry.path().join("ddc").read_link().ok()?;
+            let path = Path::new(I2C_DEV_PATH).join(bus.file_name()?);
+            is_i2c_device(&path).then_some(DeviceDetail {
+                name,
+                path,
+                capability: Capability::DdcCi,
+            })
+        })
+        .collect::<Vec<_>>();
+    devices.sort_by(|a, b| a.name.cmp(&b.name));
+    devices
+}
+
+/// Every backlight and LED device, and every DDC/CI device.
+pub fn find_devices(sysfs: &Sysfs) -> Vec<DeviceDetail> {
+    DEVICE_CLASSES
+        .iter()
+        .flat_map(|class| sysfs.join(class).read_dir())
+        .flatten()
+        .filter_map(|res| res.ok())
+        .filter_map(|entry| DeviceDetail::try_from(entry.path()).ok())
+        .chain(find_ddc_devices(sysfs))
+        .collect()
+}
+
+/// The most preferable of the discovered devices, see [`DeviceDetail::default_priority`].
+pub fn default_device(found: &[DeviceDetail]) -> crate::Result<DeviceDetail> {
+    found
+        .iter()
+        .min_by_key(|detail| detail.default_priority())
+        .cloned()
+        .ok_or(crate::Error::NoDefaultDevice)
+}
+
+/// Like [`select_devices`], but only allowing backlight devices.
+pub fn select_backlights(
+    patterns: &[String],
+    config: &Config,
+    found: &FoundDevices,
+) -> crate::Result<Vec<DeviceDetail>> {
+    let devices = select_devices(patterns, config, found)?;
+    match devices
+        .iter()
+        .find(|detail| !matches!(detail.capability, Capability::Backlight))
+    {
+        Some(detail) => Err(crate::Error::NotABacklight(detail.name.clone())),
+        None => Ok(devices),
+    }
+}
+
+/// Like [`select_devices`], but only allowing LED devices, which are opened.
+pub fn select_leds(
+    patterns: &[String],
+    config: &Config,
+    found: &FoundDevices,
+) -> crate::Result<Vec<(DeviceDetail, LedDevice)>> {
+    select_devices(patterns, config, found)?
+        .into_iter()
+        .map(|detail| match detail.capability {
+            Capability::Brightness | Capability::Multicolor
+                if detail.path.join("trigger").exists() =>
+            {
+                let led = LedDevice::new(detail.path.clone());
+                Ok((detail, led))
+            }
+            _ => Err(crate::Error::NotAnLed(detail.name)),
+        })
+        .collect()
+}
+
+/// The paths of the devices that are used when none are given.
+pub fn default_paths(config: &Config, found: &FoundDevices) -> Vec<PathBuf> {
+    select_devices(&[], config, found)
+        .map(|devices| devices.into_iter().map(|device| device.path).collect())
+        .unwrap_or_default()
+}
+
+/// Resolve the device arguments to a list of unique devices.
+///
+/// Arguments containing a `/` are treated as (possibly glob) paths, where
+/// paths in `/sys` are within the sysfs of `found`, and anything else is
+/// matched against the names of discovered devices.
+/// Aliases from the configuration are replaced by what they stand for.
+/// When no arguments are given, the device from the configuration
+/// is used, or else the default device.
+pub fn select_devices(
+    patterns: &[String],
+    config: &Config,
+    found: &FoundDevices,
+) -> crate::Result<Vec<DeviceDetail>> {
+    let patterns = match (patterns, &config.device) {
+        ([], Some(device)) => std::slice::from_ref(device),
+        ([], None) => return Ok(vec![default_device(found)?]),
+        (patterns, _) => patterns,
+    };
+
+    let mut selected = Vec::<DeviceDetail>::new();
+    for pattern in patterns {
+        let resolved = config.resolve_alias(pattern);
+        let invalid = |e| crate::Error::InvalidDevicePattern(pattern.clone(), e);
+        let matches: Vec<DeviceDetail> = if resolved.contains('/') {
+            glob::glob(&found.sysfs.join_pattern(resolved))
+                .map_err(invalid)?
+                .filter_map(|res| res.ok())
+                .filter_map(|path| DeviceDetail::try_from(path).ok())
+                .collect()
+        } else {
+            let resolved = glob::Pattern::new(resolved).map_err(invalid)?;
+            found
+                .iter()
+                .filter(|device| resolved.matches(&device.name))
+                .cloned()
+                .collect()
+        };
+        if matches.is_empty() {
+            return Err(crate::Error::NoMatchingDevice(pattern.clone()));
+        }
+        for device in matches {
+            if !selected.iter().any(|other| other.path == device.path) {
+                selected.push(device);
+            }
+        }
+    }
+    Ok(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use test_case::test_case;
+
+    use super::{default_device, find_devices, select_devices, DeviceDetail, FoundDevices, Sysfs};
+    use crate::config::Config;
+
+    /// Create a fake sysfs device with the given attributes.
+    fn fake_device(root: &Path, name: &str, attributes: &[(&str, &str)]) -> DeviceDetail {
+        let path = root.join(name);
+        std::fs::create_dir_all(&path).unwrap();
+        for (attribute, contents) in [("brightness", "0"), ("max_brightness", "100")]
+            .iter()
+            .chain(attributes)
+        {
+            let file = path.join(attribute);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, contents).unwrap();
+        }
+        DeviceDetail::try_from(path).unwrap()
+    }
+
+    fn backlight(kind: &str) -> Vec<(&str, &str)> {
+        vec![
+            ("actual_brightness", "0"),
+            ("bl_power", "0"),
+            ("type", kind),
+        ]
+    }
+
+    #[test_case(&["input3::capslock", "intel_backlight"] => "intel_backlight")]
+    #[test_case(&["intel_backlight", "acpi_video0", "dell_backlight"] => "acpi_video0")]
+    #[test_case(&["intel_backlight", "dell_backlight"] => "dell_backlight")]
+    #[test_case(&["intel_backlight", "nvidia_0"] => "nvidia_0")]
+    #[test_case(&["nvidia_0", "amdgpu_bl0"] => "amdgpu_bl0")]
+    #[test_case(&["nvidia_0", "acpi_video1"] => "acpi_video1" ; "type before connector")]
+    fn test_default_priority(names: &[&str]) -> String {
+        let root = tempfile::tempdir().unwrap();
+        let devices = names
+            .iter()
+            .map(|&name| {
+                let attributes = match name {
+                    "acpi_video0" => backlight("firmware"),
+                    "acpi_video1" => {
+                        [backlight("firmware"), vec![("device/enabled", "disabled")]].concat()
+                    }
+                    "dell_backlight" => backlight("platform"),
+                    "intel_backlight" => {
+                        [backlight("raw"), vec![("device/enabled", "disabled")]].concat()
+                    }
+                    "nvidia_0" | "amdgpu_bl0" => {
+                        [backlight("raw"), vec![("device/enabled", "enabled")]].concat()
+                    }
+                    _ => Vec::new(),
+                };
+                fake_device(root.path(), name, &attributes)
+            })
+            .collect::<Vec<_>>();
+        default_device(&devices).unwrap().name
+    }
+
+    #[test_case("class/leds" => "/fake/sys/class/leds")]
+    #[test_case("/sys/class/leds" => "/fake/sys/class/leds")]
+    #[test_case("/dev/i2c-1" => "/dev/i2c-1")]
+    fn test_sysfs_join(path: &str) -> String {
+        let sysfs = Sysfs::new("/fake/sys");
+        sysfs.join(path).display().to_string()
+    }
+
+    #[test_case("/sys/class/leds/*" => "/fake/[[]sys[]]/class/leds/*")]
+    #[test_case("/sysfs/*" => "/sysfs/*")]
+    #[test_case("/dev/i2c-*" => "/dev/i2c-*")]
+    fn test_sysfs_join_pattern(pattern: &str) -> String {
+        Sysfs::new("/fake/[sys]/").join_pattern(pattern)
+    }
+
+    #[test]
+    fn test_find_devices_in_sysfs() {
+        let root = tempfile::tempdir().unwrap();
+        let sysfs = Sysfs::new(root.path());
+        fake_device(
+            &sysfs.join("class/backlight"),
+            "acpi_video0",
+            &backlight("firmware"),
+        );
+        fake_device(&sysfs.join("class/leds"), "input3::capslock", &[]);
+        std::fs::create_dir_all(sysfs.join("class/leds/not_a_device")).unwrap();
+
+        let mut names = find_devices(&sysfs)
+            .into_iter()
+            .map(|device| device.name)
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["acpi_video0", "input3::capslock"]);
+
+        let found = FoundDevices::new(sysfs.clone());
+        let patterns = ["/sys/class/leds/input3::*".to_owned()];
+        let selected = select_devices(&patterns, &Config::default(), &found).unwrap();
+        assert_eq!(selected[0].path, sysfs.join("class/leds/input3::capslock"));
+    }
+}
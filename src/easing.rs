@@ -0,0 +1,90 @@
+use strum::{Display, EnumString};
+
+/// The rate of change over the course of an interpolated transition.
+///
+/// Easing is applied to perceived brightness, so it composes with
+/// whichever [`Curve`](crate::curve::Curve) is active.
+#[derive(EnumString, Display, PartialEq, Debug, Clone, Copy, Default)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Easing {
+    /// Constant rate of change.
+    #[default]
+    Linear,
+    /// Start slowly and accelerate (quadratic).
+    EaseIn,
+    /// Start quickly and decelerate (quadratic).
+    EaseOut,
+    /// Accelerate until halfway, then decelerate (quadratic).
+    EaseInOut,
+    /// Like `ease-in-out`, but cubic.
+    Cubic,
+    /// Start very slowly and accelerate exponentially.
+    Exponential,
+}
+
+impl Easing {
+    /// Map the linear progress `t` (between `0.0` and `1.0`) to eased progress.
+    pub fn apply(self, t: f64) -> f64 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(2),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Easing::Cubic => {
+                if t < 0.5 {
+                    4.0 * t.powi(3)
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Easing::Exponential => (2_f64.powf(10.0 * t) - 1.0) / 1023.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::{test_case, test_matrix};
+
+    use super::Easing;
+
+    #[test_case("linear" => Easing::Linear)]
+    #[test_case("ease-in" => Easing::EaseIn)]
+    #[test_case("ease-out" => Easing::EaseOut)]
+    #[test_case("ease-in-out" => Easing::EaseInOut)]
+    #[test_case("cubic" => Easing::Cubic)]
+    #[test_case("exponential" => Easing::Exponential)]
+    fn test_parse_easing(input: &str) -> Easing {
+        input.parse().unwrap()
+    }
+
+    #[test_matrix([
+        Easing::Linear,
+        Easing::EaseIn,
+        Easing::EaseOut,
+        Easing::EaseInOut,
+        Easing::Cubic,
+        Easing::Exponential
+    ])]
+    fn test_easing_endpoints_and_monotonic(easing: Easing) {
+        assert_eq!(easing.apply(0.0), 0.0);
+        assert!((easing.apply(1.0) - 1.0).abs() < 1e-12);
+        let samples = (0..=100).map(|i| easing.apply(i as f64 / 100.0));
+        let samples = samples.collect::<Vec<_>>();
+        assert!(samples.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test_case(Easing::EaseIn => true)]
+    #[test_case(Easing::EaseOut => false)]
+    #[test_case(Easing::Exponential => true)]
+    fn test_easing_slow_start(easing: Easing) -> bool {
+        easing.apply(0.25) < 0.25
+    }
+}
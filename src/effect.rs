@@ -0,0 +1,321 @@
+use std::sync::PoisonError;
+use std::time::{Duration, Instant};
+
+use crate::clock::Clock;
+use crate::curve::Curve;
+use crate::device::{Brightness, Led, PatternTrigger};
+use crate::discovery::DeviceDetail;
+use crate::ramp::{
+    ramp_brightness, CancelToken, ExternalChange, InterpolationOptions, RampTracker,
+};
+use crate::{read_brightness, read_triggers, write_brightness, BoxedDevice, Error, Result};
+
+/// How often a pause between steps checks whether it was cancelled.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const PATTERN_TRIGGER: &str = "pattern";
+const NO_TRIGGER: &str = "none";
+
+/// One step of an [`Effect`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub brightness: u32,
+    /// The time until the next step, over which the brightness changes
+    /// to that of the next step. A step with no duration is used to
+    /// change the brightness at once, as with the kernel `pattern` trigger.
+    pub duration: Duration,
+}
+
+/// A repeating sequence of brightness changes, run in software.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Effect {
+    pub steps: Vec<Step>,
+    /// The number of times to repeat the steps, or until cancelled.
+    pub cycles: Option<u32>,
+}
+
+impl Effect {
+    /// Go through each brightness for its duration, either holding it,
+    /// or fading to the next brightness if `fade` is set.
+    pub fn pattern(steps: &[(u32, Duration)], fade: bool, cycles: Option<u32>) -> Self {
+        let steps = steps
+            .iter()
+            .flat_map(|&(brightness, duration)| {
+                let hold = (!fade).then(|| Step::new(brightness, Duration::ZERO));
+                std::iter::once(Step::new(brightness, duration)).chain(hold)
+            })
+            .collect();
+        Self { steps, cycles }
+    }
+
+    /// Switch between `on` and `off`, for half of each period.
+    pub fn blink(on: u32, off: u32, period: Duration, cycles: Option<u32>) -> Self {
+        let half = period / 2;
+        Self::pattern(&[(on, half), (off, half)], false, cycles)
+    }
+
+    /// Fade from `off` to `on` and back, for half of each period.
+    pub fn pulse(on: u32, off: u32, period: Duration, cycles: Option<u32>) -> Self {
+        let half = period / 2;
+        Self::pattern(&[(off, half), (on, half)], true, cycles)
+    }
+
+    /// The effect as the attributes of the kernel `pattern` trigger,
+    /// which repeats it at most `i32::MAX` times.
+    pub fn to_pattern_trigger(&self) -> Result<PatternTrigger> {
+        let repeat = match self.cycles {
+            Some(cycles) => i32::try_from(cycles).map_err(|_| Error::TooManyCycles(cycles))?,
+            None => -1,
+        };
+        Ok(PatternTrigger {
+            pattern: self
+                .steps
+                .iter()
+                .map(|step| (step.brightness, step.duration.as_millis() as u32))
+                .collect(),
+            repeat,
+        })
+    }
+
+    /// Run the effect with the `pattern` trigger of `led`, in hardware if it
+    /// can, or else timed by the kernel. Without the trigger, or if the
+    /// pattern cannot be written, the trigger is left as it was.
+    ///
+    /// Returns whether the LED has the trigger.
+    pub fn run_pattern_trigger(&self, led: &dyn Led) -> Result<bool> {
+        let triggers = read_triggers(led)?;
+        if !triggers.available.iter().any(|t| t == PATTERN_TRIGGER) {
+            return Ok(false);
+        }
+        let pattern = self.to_pattern_trigger()?;
+        let write_failed = |e| Error::DeviceWriteFailed("trigger", e);
+        led.set_trigger(PATTERN_TRIGGER).map_err(write_failed)?;
+        let (attribute, result) = if led.has_hw_pattern() {
+            ("hw_pattern", led.set_hw_pattern(&pattern))
+        } else {
+            ("pattern", led.set_pattern(&pattern))
+        };
+        if let Err(e) = result {
+            let previous = triggers.current.as_deref().unwrap_or(NO_TRIGGER);
+            led.set_trigger(previous).map_err(write_failed)?;
+            return Err(Error::DeviceWriteFailed(attribute, e));
+        }
+        Ok(true)
+    }
+
+    /// Run every cycle of the effect, until finished or cancelled.
+    ///
+    /// Fades use the frequency and easing of `interpolate`, along `curve`,
+    /// and are never stopped by the brightness being changed externally.
+    /// Steps are timed by `clock`.
+    pub fn run(
+        &self,
+        device: &dyn Brightness,
+        interpolate: &InterpolationOptions,
+        curve: &Curve,
+        clock: &dyn Clock,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        let interpolate = InterpolationOptions {
+            external_change: ExternalChange::Force,
+            ..interpolate.clone()
+        };
+        let mut cycle = 0;
+        while !self.steps.is_empty() && self.cycles.is_none_or(|cycles| cycle < cycles) {
+            for (i, step) in self.steps.iter().enumerate() {
+                if cancel.is_cancelled() {
+                    return Ok(());
+                }
+                let deadline = clock.now() + step.duration;
+                if read_brightness(device)? != step.brightness {
+                    write_brightness(device, step.brightness)?;
+                }
+                let next = self.steps[(i + 1) % self.steps.len()].brightness;
+                if next != step.brightness && !step.duration.is_zero() {
+                    let duration = step.duration;
+                    ramp_brightness(device, next, duration, &interpolate, curve, clock, cancel)?;
+                }
+                sleep_until(clock, deadline, cancel);
+            }
+            cycle += 1;
+        }
+        Ok(())
+    }
+}
+
+/// An effect to run on a single device, see [`run_effects`].
+pub struct EffectRun<'a> {
+    pub detail: DeviceDetail,
+    pub device: BoxedDevice,
+    pub curve: &'a Curve,
+    pub effect: Effect,
+}
+
+impl Step {
+    pub fn new(brightness: u32, duration: Duration) -> Self {
+        Self {
+            brightness,
+            duration,
+        }
+    }
+}
+
+/// Run every effect at once, until they finish or `interrupted` is cancelled,
+/// after which the brightness of each device is restored. A device is left
+/// alone if another ramp takes it over through `tracker` instead.
+pub fn run_effects(
+    runs: Vec<EffectRun>,
+    interpolate: &InterpolationOptions,
+    tracker: &RampTracker,
+    clock: &dyn Clock,
+    interrupted: &CancelToken,
+) -> Result<()> {
+    std::thread::scope(|scope| {
+        let handles = runs
+            .into_iter()
+            .map(|run| {
+                let (cancel, running) = tracker.begin(&run.detail.path);
+                let cancel = cancel.within(interrupted);
+                scope.spawn(move || {
+                    let _running = running.lock().unwrap_or_else(PoisonError::into_inner);
+                    let _lock = tracker.lock(&run.detail.path, &cancel)?;
+                    let device = &*run.device;
+                    let original = read_brightness(device)?;
+                    let result = run
+                        .effect
+                        .run(device, interpolate, run.curve, clock, &cancel);
+                    if interrupted.is_cancelled() || !cancel.is_cancelled() {
+                        write_brightness(device, original)?;
+                    }
+                    result
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("effect thread panicked"))
+    })
+}
+
+/// Sleep on `clock` until `deadline`, or until cancelled.
+fn sleep_until(clock: &dyn Clock, deadline: Instant, cancel: &CancelToken) {
+    loop {
+        let now = clock.now();
+        if now >= deadline || cancel.is_cancelled() {
+            return;
+        }
+        clock.sleep_until(deadline.min(now + CANCEL_POLL_INTERVAL));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::time::Duration;
+
+    use super::Effect;
+    use crate::clock::{Clock, VirtualClock};
+    use crate::curve::Curve;
+    use crate::device::{Brightness, ReadNumResult, WriteResult};
+    use crate::ramp::{CancelToken, InterpolationOptions};
+    use crate::Error;
+
+    /// A device that records every write.
+    #[derive(Default)]
+    struct RecordingDevice {
+        value: Cell<u32>,
+        written: RefCell<Vec<u32>>,
+    }
+
+    impl Brightness for RecordingDevice {
+        fn brightness(&self) -> ReadNumResult<u32> {
+            Ok(self.value.get())
+        }
+
+        fn set_brightness(&self, value: u32) -> WriteResult {
+            self.written.borrow_mut().push(value);
+            self.value.set(value);
+            Ok(())
+        }
+
+        fn max_brightness(&self) -> ReadNumResult<u32> {
+            Ok(100)
+        }
+    }
+
+    #[test]
+    fn test_blink() {
+        let device = RecordingDevice::default();
+        let effect = Effect::blink(80, 10, Duration::from_secs(2), Some(3));
+        let (interpolate, cancel) = (InterpolationOptions::default(), CancelToken::default());
+        let clock = VirtualClock::default();
+        let start = clock.now();
+        effect
+            .run(&device, &interpolate, &Curve::Linear, &clock, &cancel)
+            .unwrap();
+        assert_eq!(device.written.into_inner(), [80, 10, 80, 10, 80, 10]);
+        assert_eq!(clock.now() - start, Duration::from_secs(6));
+    }
+
+    #[test]
+    fn test_pulse() {
+        let device = RecordingDevice::default();
+        let effect = Effect::pulse(100, 0, Duration::from_secs(2), Some(1));
+        let interpolate = InterpolationOptions {
+            frequency: Some(100),
+            ..InterpolationOptions::default()
+        };
+        effect
+            .run(
+                &device,
+                &interpolate,
+                &Curve::Linear,
+                &VirtualClock::default(),
+                &CancelToken::default(),
+            )
+            .unwrap();
+        let written = device.written.into_inner();
+        let peak = written.iter().position(|&value| value == 100).unwrap();
+        assert!(written[..=peak].windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(written[peak..].windows(2).all(|pair| pair[0] > pair[1]));
+        assert_eq!(written.last(), Some(&0));
+    }
+
+    #[test]
+    fn test_pattern_trigger() {
+        let ms = Duration::from_millis;
+        let blink = Effect::blink(255, 0, ms(1000), None);
+        let trigger = blink.to_pattern_trigger().unwrap();
+        assert_eq!(trigger.pattern, [(255, 500), (255, 0), (0, 500), (0, 0)]);
+        assert_eq!(trigger.repeat, -1);
+
+        let fade = Effect::pattern(&[(10, ms(100)), (20, ms(200))], true, Some(3));
+        let trigger = fade.to_pattern_trigger().unwrap();
+        assert_eq!(trigger.pattern, [(10, 100), (20, 200)]);
+        assert_eq!(trigger.repeat, 3);
+
+        let endless = Effect::blink(255, 0, ms(1000), Some(u32::MAX));
+        assert!(matches!(
+            endless.to_pattern_trigger(),
+            Err(Error::TooManyCycles(u32::MAX))
+        ));
+    }
+
+    #[test]
+    fn test_cancelled_effect() {
+        let device = RecordingDevice::default();
+        let cancel = CancelToken::default();
+        cancel.cancel();
+        let effect = Effect::blink(80, 10, Duration::from_secs(10), None);
+        effect
+            .run(
+                &device,
+                &InterpolationOptions::default(),
+                &Curve::Linear,
+                &VirtualClock::default(),
+                &cancel,
+            )
+            .unwrap();
+        assert!(device.written.into_inner().is_empty());
+    }
+}
@@ -0,0 +1,28 @@
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Any error of the `slight` command, which is either one of the library,
+/// or one of parsing arguments, writing output, or talking to the daemon.
+#[derive(thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Slight(#[from] slight::Error),
+    #[error("the arguments are incorrect: {0}")]
+    MalformedArguments(Box<dyn std::error::Error + Send + Sync>),
+    #[error("writing output failed: {0}")]
+    OutputFailed(std::io::Error),
+    #[error("daemon socket failed: {0}")]
+    DaemonSocketFailed(std::io::Error),
+    #[error("{0}")]
+    DaemonCommandFailed(String),
+    #[error("failed to handle signals: {0}")]
+    SignalFailed(std::io::Error),
+}
+
+// printed when `main` fails, as the message rather than the variant
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,107 @@
+use strum::{Display, EnumString};
+
+use slight::config::GammastepHookConfig;
+use slight::ramp::InterpolationOptions;
+use slight::value::DurationArgument;
+
+use crate::cli::Action;
+
+/// The only event that Gammastep and Redshift currently send to hooks.
+pub const PERIOD_CHANGED: &str = "period-changed";
+
+/// A period of the day, as named in the arguments to Gammastep and Redshift hooks.
+#[derive(Debug, Display, EnumString, PartialEq, Clone, Copy)]
+#[strum(serialize_all = "lowercase")]
+pub enum Period {
+    None,
+    Daytime,
+    Transition,
+    Night,
+}
+
+/// The command to run when the period changes from `old` to `new`, if any.
+///
+/// Changes between adjacent periods are ramped, and only ever move the
+/// brightness towards the new period, so that a manual adjustment
+/// in that direction is kept. Any other change is applied immediately.
+pub fn period_changed(config: &GammastepHookConfig, old: Period, new: Period) -> Option<Action> {
+    let (value, increase, decrease, duration) = match (old, new) {
+        (Period::Transition, Period::Daytime) => (
+            config.daytime,
+            true,
+            false,
+            config.daytime_from_transition.clone(),
+        ),
+        (Period::Daytime, Period::Transition) => (
+            config.transition,
+            false,
+            true,
+            config.transition_from_daytime.clone(),
+        ),
+        (Period::Night, Period::Transition) => (
+            config.transition,
+            true,
+            false,
+            config.transition_from_night.clone(),
+        ),
+        (Period::Transition, Period::Night) => (
+            config.night,
+            false,
+            true,
+            config.night_from_transition.clone(),
+        ),
+        (old, new) if old == new => return None,
+        (_, Period::Daytime) => (config.daytime, false, false, DurationArgument::ZERO),
+        (_, Period::Transition) => (config.transition, false, false, DurationArgument::ZERO),
+        (_, Period::Night) => (config.night, false, false, DurationArgument::ZERO),
+        (_, Period::None) => return None,
+    };
+    Some(Action::Set {
+        increase,
+        decrease,
+        interpolate: InterpolationOptions {
+            duration: Some(duration),
+            ..InterpolationOptions::default()
+        },
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use test_case::test_case;
+
+    use slight::config::GammastepHookConfig;
+    use slight::value::Value;
+
+    use super::{period_changed, Period};
+    use crate::cli::Action;
+
+    #[test_case(Period::Transition, Period::Daytime => Some((Value::Percent(0.85), true, false, 5)))]
+    #[test_case(Period::Daytime, Period::Transition => Some((Value::Percent(0.55), false, true, 5)))]
+    #[test_case(Period::Night, Period::Transition => Some((Value::Percent(0.55), true, false, 20)))]
+    #[test_case(Period::Transition, Period::Night => Some((Value::Percent(0.25), false, true, 10)))]
+    #[test_case(Period::None, Period::Night => Some((Value::Percent(0.25), false, false, 0)))]
+    #[test_case(Period::Daytime, Period::Night => Some((Value::Percent(0.25), false, false, 0)))]
+    #[test_case(Period::Night, Period::Daytime => Some((Value::Percent(0.85), false, false, 0)))]
+    #[test_case(Period::None, Period::Transition => Some((Value::Percent(0.55), false, false, 0)))]
+    #[test_case(Period::Night, Period::Night => None)]
+    #[test_case(Period::Daytime, Period::None => None)]
+    fn test_period_changed(old: Period, new: Period) -> Option<(Value, bool, bool, u64)> {
+        match period_changed(&GammastepHookConfig::default(), old, new)? {
+            Action::Set {
+                increase,
+                decrease,
+                interpolate,
+                value,
+            } => Some((value, increase, decrease, interpolate.duration().as_secs())),
+            action => panic!("unexpected action: {action:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse_period() {
+        assert_eq!("daytime".parse(), Ok(Period::Daytime));
+        assert!("day".parse::<Period>().is_err());
+    }
+}
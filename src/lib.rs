@@ -0,0 +1,177 @@
+//! Control the brightness of backlight, LED, and DDC/CI devices on Linux.
+//!
+//! Devices are found with [`discovery`], and opened with [`open_device`] as
+//! a [`Brightness`], or as one of the more specific traits in [`device`].
+//! Brightness is given as a [`Value`](value::Value), which is mapped to the
+//! range of a device along a [`Curve`](curve::Curve), and changed gradually
+//! with the ramps of [`ramp`], or those of `async_ramp` with the `tokio` feature.
+//!
+//! ```
+//! use slight::curve::Curve;
+//! use slight::value::Value;
+//!
+//! let value: Value = "50%".parse().unwrap();
+//! assert_eq!(value.to_absolute(255, &Curve::Linear), 128);
+//! ```
+
+/// Ramps that run as tasks of a tokio runtime, with the `tokio` feature.
+#[cfg(feature = "tokio")]
+pub mod async_ramp;
+/// Ambient light sensors, and the mapping from illuminance to brightness.
+pub mod auto;
+/// Sources of time for ramps, including a virtual one for tests.
+pub mod clock;
+/// The colors of multicolor LEDs.
+pub mod color;
+/// The configuration file.
+pub mod config;
+/// Mappings between perceived brightness and the values of devices.
+pub mod curve;
+/// External displays controlled over DDC/CI.
+pub mod ddc;
+/// The sysfs interfaces of backlight and LED devices.
+pub mod device;
+/// Finding devices, and selecting them by name, path, or pattern.
+pub mod discovery;
+/// Rates of change for ramps.
+pub mod easing;
+/// Repeating brightness effects, such as blinking.
+pub mod effect;
+/// Locks that stop other processes from changing a device at the same time.
+pub mod lock;
+/// Writing brightness through systemd-logind, without write access to sysfs.
+pub mod logind;
+/// Gradual changes of brightness.
+pub mod ramp;
+/// Saved brightness, to be restored later.
+pub mod state;
+/// The state of a device, as shown by `slight list` and `slight get`.
+pub mod status;
+/// Brightness values and durations, as written in arguments and configuration.
+pub mod value;
+
+use self::color::ColorError;
+use self::config::ConfigError;
+use self::ddc::DdcDevice;
+use self::device::{Backlight, BacklightDevice, Brightness, Led, LedDevice, PowerState, Triggers};
+use self::discovery::{Capability, DeviceDetail, Sysfs};
+use self::logind::{device_subsystem, LogindDevice, LogindSession};
+use self::state::StateError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Any error of the library.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("failed to find a default device")]
+    NoDefaultDevice,
+    #[error("no devices match '{0}'")]
+    NoMatchingDevice(String),
+    #[error("invalid device pattern '{0}': {1}")]
+    InvalidDevicePattern(String, glob::PatternError),
+    #[error("failed to open device '{0}': {1}")]
+    DeviceOpenFailed(String, std::io::Error),
+    #[error("reading device attribute '{0}' failed: {1}")]
+    DeviceReadFailed(&'static str, Box<dyn std::error::Error + Send + Sync>),
+    #[error("writing device attribute '{0}' failed: {1}")]
+    DeviceWriteFailed(&'static str, std::io::Error),
+    #[error("failed to lock device: {0}")]
+    DeviceLockFailed(std::io::Error),
+    #[error("{0}")]
+    ConfigFailed(ConfigError),
+    #[error("failed to find an ambient light sensor")]
+    NoLightSensor,
+    #[error("{0}")]
+    StateFailed(StateError),
+    #[error("no brightness has been saved for '{0}'")]
+    NoSavedState(String),
+    #[error("'{0}' is not a backlight device")]
+    NotABacklight(String),
+    #[error("'{0}' is not an LED device")]
+    NotAnLed(String),
+    #[error("unknown trigger '{0}' for '{1}'")]
+    UnknownTrigger(String, String),
+    #[error("'{0}' is not a multicolor LED device")]
+    NotMulticolor(String),
+    #[error("invalid color for '{0}': {1}")]
+    InvalidColor(String, ColorError),
+    #[error("the pattern trigger cannot repeat {0} times")]
+    TooManyCycles(u32),
+}
+
+/// A device of any kind, which can be moved to the thread of a ramp.
+pub type BoxedDevice = Box<dyn Brightness + Send>;
+
+/// Open a device, falling back to writing through logind for sysfs
+/// devices when writing directly is denied, or always if `logind` is set.
+pub fn open_device(detail: &DeviceDetail, logind: bool) -> Result<BoxedDevice> {
+    let path = detail.path.clone();
+    Ok(match detail.capability {
+        Capability::Backlight => with_logind(BacklightDevice::new(path), detail, logind),
+        Capability::DdcCi => Box::new(
+            DdcDevice::open(&path)
+                .map_err(|e| Error::DeviceOpenFailed(path.display().to_string(), e))?,
+        ),
+        _ => with_logind(LedDevice::new(path), detail, logind),
+    })
+}
+
+fn with_logind<D>(device: D, detail: &DeviceDetail, forced: bool) -> BoxedDevice
+where
+    D: Brightness + Send + 'static,
+{
+    // logind only knows of the devices in the real sysfs
+    if !detail.path.starts_with(Sysfs::default().root()) {
+        return Box::new(device);
+    }
+    let Some(subsystem) = device_subsystem(&detail.path) else {
+        return Box::new(device);
+    };
+    let device = LogindDevice::new(
+        device,
+        LogindSession::system(),
+        subsystem,
+        detail.name.clone(),
+    );
+    if forced {
+        Box::new(device.forced())
+    } else {
+        Box::new(device)
+    }
+}
+
+pub fn read_brightness(device: &dyn Brightness) -> Result<u32> {
+    device
+        .brightness()
+        .map_err(|e| Error::DeviceReadFailed("brightness", e.into()))
+}
+
+pub fn read_max_brightness(device: &dyn Brightness) -> Result<u32> {
+    device
+        .max_brightness()
+        .map_err(|e| Error::DeviceReadFailed("max_brightness", e.into()))
+}
+
+pub fn write_brightness(device: &dyn Brightness, value: u32) -> Result<()> {
+    device
+        .set_brightness(value)
+        .map_err(|e| Error::DeviceWriteFailed("brightness", e))
+}
+
+pub fn read_bl_power(device: &dyn Backlight) -> Result<PowerState> {
+    device
+        .bl_power()
+        .map_err(|e| Error::DeviceReadFailed("bl_power", e.into()))
+}
+
+pub fn write_bl_power(device: &dyn Backlight, value: PowerState) -> Result<()> {
+    device
+        .set_bl_power(value)
+        .map_err(|e| Error::DeviceWriteFailed("bl_power", e))
+}
+
+pub fn read_triggers(device: &dyn Led) -> Result<Triggers> {
+    device
+        .triggers()
+        .map_err(|e| Error::DeviceReadFailed("trigger", e.into()))
+}
@@ -0,0 +1,252 @@
+use std::fs::{DirBuilder, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use signal_hook::consts::SIGUSR1;
+use signal_hook::iterator::Signals;
+
+use crate::ramp::CancelToken;
+
+/// Locks held by this process, and the ramps to cancel when another process takes them.
+static HELD: Mutex<Vec<(PathBuf, CancelToken)>> = Mutex::new(Vec::new());
+/// Whether the thread that handles `SIGUSR1` has been started.
+static WATCHER: Mutex<bool> = Mutex::new(false);
+
+/// An exclusive lock on a device, shared between processes with a lock file.
+///
+/// The lock file contains the PID of the process that holds the lock, or of
+/// the process waiting to take it over. When a process finds the lock held,
+/// it writes its own PID to the file and sends `SIGUSR1` to the holder, which
+/// then cancels its ramp for that device and releases the lock. The holder is
+/// only signalled if `/proc/locks` confirms that it holds the lock, since
+/// the PID in the file may be stale, and the signal would kill any other process.
+#[derive(Debug)]
+pub struct DeviceLock {
+    file: File,
+    path: PathBuf,
+    cancel: CancelToken,
+}
+
+impl DeviceLock {
+    /// Take the lock for the device at `device`, waiting for any other
+    /// process to stop. The lock is released when this is dropped, and
+    /// `cancel` is cancelled if another process takes over before then.
+    pub fn acquire(device: &Path, cancel: &CancelToken) -> std::io::Result<Self> {
+        let path = lock_path(device)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(&path)?;
+
+        // once our PID is in the file, another process may signal us,
+        // which would kill us without a handler
+        start_watcher()?;
+        if !flock(&file, libc::LOCK_EX | libc::LOCK_NB)? {
+            if let Some(holder) = read_pid(&mut file) {
+                write_pid(&mut file, std::process::id())?;
+                // the PID may be of a waiter that gave up, or reused since
+                if holds_lock(holder, &file) {
+                    // SAFETY: `kill` has no memory safety requirements.
+                    unsafe { libc::kill(holder as libc::pid_t, SIGUSR1) };
+                }
+            }
+            flock(&file, libc::LOCK_EX)?;
+        }
+        HELD.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((path.clone(), cancel.clone()));
+        write_pid(&mut file, std::process::id())?;
+
+        Ok(Self {
+            file,
+            path,
+            cancel: cancel.clone(),
+        })
+    }
+}
+
+impl Drop for DeviceLock {
+    fn drop(&mut self) {
+        HELD.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|(path, cancel)| !(path == &self.path && cancel.same(&self.cancel)));
+        // the lock itself is released when the file is closed
+        _ = self.file.flush();
+    }
+}
+
+/// The directory of this user for lock files and the socket of the daemon,
+/// in `$XDG_RUNTIME_DIR` if it is set, or else in the temporary directory.
+///
+/// The directory is created if it does not exist, and refused unless it is
+/// owned by this user and inaccessible to anyone else, because the path in
+/// the temporary directory is predictable, and could be made by another user.
+pub fn runtime_dir() -> std::io::Result<PathBuf> {
+    // SAFETY: `getuid` is always successful.
+    let uid = unsafe { libc::getuid() };
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("slight"),
+        None => std::env::temp_dir().join(format!("slight-{uid}")),
+    };
+    create_private_dir(&dir, uid)?;
+    Ok(dir)
+}
+
+/// Create a directory only accessible to `uid`, or make sure that it is.
+fn create_private_dir(dir: &Path, uid: libc::uid_t) -> std::io::Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => return Err(e),
+        _ => {}
+    }
+    let meta = dir.symlink_metadata()?;
+    if meta.is_dir() && meta.uid() == uid {
+        // left by an earlier version, which did not restrict access
+        if meta.mode() & 0o077 != 0 {
+            std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+        }
+        return Ok(());
+    }
+    let message = format!("{} is not a private directory of this user", dir.display());
+    Err(std::io::Error::new(
+        std::io::ErrorKind::PermissionDenied,
+        message,
+    ))
+}
+
+/// The lock file for a device, named after its canonical path,
+/// so that every path to the same device uses the same lock.
+fn lock_path(device: &Path) -> std::io::Result<PathBuf> {
+    let device = device.canonicalize().unwrap_or_else(|_| device.to_owned());
+    let name = device
+        .to_string_lossy()
+        .trim_start_matches('/')
+        .replace('/', "!");
+    Ok(runtime_dir()?.join(format!("{name}.lock")))
+}
+
+/// Returns `false` if the lock would block.
+fn flock(file: &File, operation: libc::c_int) -> std::io::Result<bool> {
+    // SAFETY: the file descriptor is valid for the lifetime of `file`.
+    if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+        return Ok(true);
+    }
+    match std::io::Error::last_os_error() {
+        e if e.kind() == std::io::ErrorKind::WouldBlock => Ok(false),
+        e => Err(e),
+    }
+}
+
+/// Whether the process `pid` holds the `flock` of `file`, as listed in `/proc/locks`.
+fn holds_lock(pid: u32, file: &File) -> bool {
+    let Ok(meta) = file.metadata() else {
+        return false;
+    };
+    let (major, minor) = (libc::major(meta.dev()), libc::minor(meta.dev()));
+    let id = format!("{major:02x}:{minor:02x}:{}", meta.ino());
+    let Ok(locks) = std::fs::read_to_string("/proc/locks") else {
+        return false;
+    };
+    // such as `1: FLOCK  ADVISORY  WRITE 1234 00:1f:5678 0 EOF`, where
+    // waiting processes have `->` after the number, and are not matched
+    locks.lines().any(|line| {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        matches!(
+            fields.as_slice(),
+            [_, "FLOCK", _, _, holder, file, ..] if holder.parse() == Ok(pid) && *file == id
+        )
+    })
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut buf = String::new();
+    file.rewind().ok()?;
+    file.read_to_string(&mut buf).ok()?;
+    buf.trim().parse().ok()
+}
+
+fn write_pid(file: &mut File, pid: u32) -> std::io::Result<()> {
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{pid}")?;
+    file.flush()
+}
+
+/// Start the thread of [`spawn_watcher`], unless it already is.
+fn start_watcher() -> std::io::Result<()> {
+    let mut started = WATCHER.lock().unwrap_or_else(PoisonError::into_inner);
+    if !*started {
+        spawn_watcher()?;
+        *started = true;
+    }
+    Ok(())
+}
+
+/// When signalled, cancel the ramp of every lock that another process has claimed.
+fn spawn_watcher() -> std::io::Result<()> {
+    let mut signals = Signals::new([SIGUSR1])?;
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            let held = HELD.lock().unwrap_or_else(PoisonError::into_inner);
+            for (path, cancel) in held.iter() {
+                let claimant = OpenOptions::new()
+                    .read(true)
+                    .custom_flags(libc::O_NOFOLLOW)
+                    .open(path)
+                    .ok()
+                    .and_then(|mut file| read_pid(&mut file));
+                if claimant.is_some_and(|pid| pid != std::process::id()) {
+                    cancel.cancel();
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::{create_private_dir, flock, holds_lock};
+
+    #[test]
+    fn test_create_private_dir() {
+        let root = tempfile::tempdir().unwrap();
+        // SAFETY: `getuid` is always successful.
+        let uid = unsafe { libc::getuid() };
+        let dir = root.path().join("private");
+        create_private_dir(&dir, uid).unwrap();
+        let mode = dir.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        create_private_dir(&dir, uid).unwrap();
+        assert!(create_private_dir(&dir, uid + 1).is_err());
+
+        let public = root.path().join("public");
+        std::fs::create_dir(&public).unwrap();
+        std::fs::set_permissions(&public, std::fs::Permissions::from_mode(0o755)).unwrap();
+        create_private_dir(&public, uid).unwrap();
+        let mode = public.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let link = root.path().join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        assert!(create_private_dir(&link, uid).is_err());
+    }
+
+    #[test]
+    fn test_holds_lock() {
+        let file = tempfile::tempfile().unwrap();
+        let pid = std::process::id();
+        assert!(!holds_lock(pid, &file));
+        assert!(flock(&file, libc::LOCK_EX).unwrap());
+        assert!(holds_lock(pid, &file));
+        assert!(!holds_lock(pid + 1, &file));
+    }
+}
@@ -0,0 +1,213 @@
+use std::cell::Cell;
+use std::path::Path;
+
+use once_cell::sync::OnceCell;
+use zbus::blocking::{Connection, Proxy};
+
+use crate::device::{Brightness, ReadNumResult, WriteResult};
+
+const LOGIND_DESTINATION: &str = "org.freedesktop.login1";
+const LOGIND_SESSION_PATH: &str = "/org/freedesktop/login1/session/auto";
+const LOGIND_SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+
+/// The current session of `systemd-logind`, which can change the
+/// brightness of backlight and LED devices on behalf of unprivileged users.
+///
+/// <https://www.freedesktop.org/software/systemd/man/latest/org.freedesktop.login1.html>
+#[derive(Debug, Default)]
+pub struct LogindSession {
+    connection: OnceCell<Connection>,
+}
+
+impl LogindSession {
+    /// The session on the system bus, which is connected to when first used.
+    pub fn system() -> Self {
+        Self::default()
+    }
+
+    /// The session on an existing connection, such as a private bus.
+    pub fn with_connection(connection: Connection) -> Self {
+        Self {
+            connection: OnceCell::with_value(connection),
+        }
+    }
+
+    pub fn set_brightness(&self, subsystem: &str, name: &str, value: u32) -> WriteResult {
+        let connection = self
+            .connection
+            .get_or_try_init(Connection::system)
+            .map_err(std::io::Error::other)?;
+        let proxy = Proxy::new(
+            connection,
+            LOGIND_DESTINATION,
+            LOGIND_SESSION_PATH,
+            LOGIND_SESSION_INTERFACE,
+        )
+        .map_err(std::io::Error::other)?;
+        proxy
+            .call_method("SetBrightness", &(subsystem, name, value))
+            .map(drop)
+            .map_err(std::io::Error::other)
+    }
+}
+
+/// A sysfs device which writes through [`LogindSession`] when
+/// writing directly is not permitted, or always if `forced`.
+#[derive(Debug)]
+pub struct LogindDevice<D: Brightness> {
+    inner: D,
+    session: LogindSession,
+    subsystem: String,
+    name: String,
+    forced: bool,
+    denied: Cell<bool>,
+}
+
+impl<D: Brightness> LogindDevice<D> {
+    pub fn new(inner: D, session: LogindSession, subsystem: String, name: String) -> Self {
+        Self {
+            inner,
+            session,
+            subsystem,
+            name,
+            forced: false,
+            denied: Cell::new(false),
+        }
+    }
+
+    /// Never attempt to write to the device directly.
+    pub fn forced(mut self) -> Self {
+        self.forced = true;
+        self
+    }
+}
+
+impl<D: Brightness> Brightness for LogindDevice<D> {
+    fn brightness(&self) -> ReadNumResult<u32> {
+        self.inner.brightness()
+    }
+
+    fn set_brightness(&self, value: u32) -> WriteResult {
+        if !self.forced && !self.denied.get() {
+            match self.inner.set_brightness(value) {
+                Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => self.denied.set(true),
+                res => return res,
+            }
+        }
+        self.session
+            .set_brightness(&self.subsystem, &self.name, value)
+    }
+
+    fn max_brightness(&self) -> ReadNumResult<u32> {
+        self.inner.max_brightness()
+    }
+
+    fn brightness_hw_changed(&self) -> Option<u32> {
+        self.inner.brightness_hw_changed()
+    }
+
+    fn is_slow_to_read(&self) -> bool {
+        self.inner.is_slow_to_read()
+    }
+}
+
+/// The kernel subsystem of a sysfs device, either `backlight` or `leds`.
+pub fn device_subsystem(path: &Path) -> Option<String> {
+    path.join("subsystem")
+        .read_link()
+        .ok()
+        .as_deref()
+        .or_else(|| path.parent())
+        .and_then(|path| path.file_name())
+        .and_then(|name| name.to_str())
+        .map(str::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Arc, Mutex};
+
+    use zbus::blocking::connection::Builder;
+    use zbus::blocking::Connection;
+    use zbus::interface;
+
+    use super::{LogindDevice, LogindSession};
+    use crate::device::{Brightness, ReadNumResult, WriteResult};
+
+    type Calls = Arc<Mutex<Vec<(String, String, u32)>>>;
+
+    struct MockSession {
+        calls: Calls,
+    }
+
+    #[interface(name = "org.freedesktop.login1.Session")]
+    impl MockSession {
+        fn set_brightness(&self, subsystem: String, name: String, brightness: u32) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push((subsystem, name, brightness));
+        }
+    }
+
+    /// Serve a mock logind session over a private peer-to-peer connection.
+    #[allow(deprecated)] // the replacement requires depending on `async-io`
+    fn mock_logind() -> (LogindSession, Calls, Connection) {
+        let calls = Calls::default();
+        let (client, server) = UnixStream::pair().unwrap();
+        let server = Builder::unix_stream(server)
+            .server(zbus::Guid::generate())
+            .unwrap()
+            .p2p()
+            .serve_at(
+                super::LOGIND_SESSION_PATH,
+                MockSession {
+                    calls: calls.clone(),
+                },
+            )
+            .unwrap();
+        let server = std::thread::spawn(move || server.build().unwrap());
+        let client = Builder::unix_stream(client).p2p().build().unwrap();
+        let server = server.join().unwrap();
+        (LogindSession::with_connection(client), calls, server)
+    }
+
+    /// A device that denies writes, like a sysfs file without udev rules.
+    struct ReadOnlyDevice;
+
+    impl Brightness for ReadOnlyDevice {
+        fn brightness(&self) -> ReadNumResult<u32> {
+            Ok(10)
+        }
+
+        fn set_brightness(&self, _value: u32) -> WriteResult {
+            Err(std::io::ErrorKind::PermissionDenied.into())
+        }
+
+        fn max_brightness(&self) -> ReadNumResult<u32> {
+            Ok(100)
+        }
+    }
+
+    #[test]
+    fn test_logind_fallback_on_permission_denied() {
+        let (session, calls, _server) = mock_logind();
+        let device = LogindDevice::new(
+            ReadOnlyDevice,
+            session,
+            "backlight".to_owned(),
+            "intel_backlight".to_owned(),
+        );
+        assert_eq!(device.brightness().unwrap(), 10);
+        device.set_brightness(42).unwrap();
+        device.set_brightness(43).unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                ("backlight".to_owned(), "intel_backlight".to_owned(), 42),
+                ("backlight".to_owned(), "intel_backlight".to_owned(), 43)
+            ]
+        );
+    }
+}
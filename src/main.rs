@@ -1,74 +1,322 @@
 mod cli;
-mod device;
-mod discovery;
+mod daemon;
+mod error;
+mod hook;
 
-use std::path::Path;
-use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
-use once_cell::unsync::Lazy;
+use signal_hook::consts::{SIGINT, SIGTERM};
+use slight::auto::{LightSensor, Smoother};
+use slight::clock::SystemClock;
+use slight::color::ColorFade;
+use slight::config::Config;
+use slight::curve::Curve;
+use slight::device::{
+    BacklightDevice, Brightness, Led, LedDevice, Multicolor, NetdevTrigger, PowerState,
+    ReadNumError, ReadNumResult, TimerTrigger, WriteResult,
+};
+use slight::discovery::{
+    default_paths, select_backlights, select_devices, select_leds, Capability, DeviceDetail,
+    FoundDevices, Sysfs, SYSFS_ROOT_ENV,
+};
+use slight::effect::{run_effects, Effect, EffectRun};
+use slight::ramp::{
+    portion, set_brightness_all, CancelToken, InterpolationOptions, RampTracker, Transition,
+};
+use slight::state::{state_path_with, SavedDevice, SavedState};
+use slight::status::DeviceStatus;
+use slight::value::{DurationArgument, Value};
+use slight::{
+    open_device, read_bl_power, read_brightness, read_max_brightness, read_triggers,
+    write_bl_power, write_brightness, BoxedDevice,
+};
 
-use crate::cli::{slight_command, Action, Value};
-use crate::device::{Backlight, BacklightDevice, Brightness, LedDevice};
-use crate::discovery::{Capability, CapabilityCheckError, DeviceDetail};
-
-use self::cli::InterpolationOptions;
-
-type Result<T> = std::result::Result<T, Error>;
-
-#[derive(Debug, thiserror::Error)]
-enum Error {
-    #[error("failed to find a default device")]
-    NoDefaultDevice,
-    #[error("reading device attribute '{0}' failed: {1}")]
-    DeviceReadFailed(&'static str, Box<dyn std::error::Error>),
-    #[error("writing device attribute '{0}' failed: {1}")]
-    DeviceWriteFailed(&'static str, std::io::Error),
-    #[error("the arguments are incorrect: {0}")]
-    MalformedArguments(Box<dyn std::error::Error>),
-}
+use self::cli::{
+    slight_command, Action, EffectOptions, Hook, PowerCommand, SlightCommand, TriggerCommand,
+};
+use self::error::{Error, Result};
+use self::hook::{Period, PERIOD_CHANGED};
 
 const CONFLICT_INCREASE_DECREASE: &str =
     "cannot specify increase (-I) and decrease (-D) at the same time";
 const CURRENT_BRIGHTNESS_GREATER: &str = "current brightness is greater than target, doing nothing";
 const CURRENT_BRIGHTNESS_LESS: &str = "current brightness is less than target, doing nothing";
+const DAEMON_ALREADY_RUNNING: &str = "this command is already running in the daemon";
+const DEVICE_NO_LONGER_EXISTS: &str = "device no longer exists, skipping";
+const TIMER_TRIGGER: &str = "timer";
+const NETDEV_TRIGGER: &str = "netdev";
+const TIMER_ATTRIBUTES_ONLY: &str = "--delay-on and --delay-off only apply to the timer trigger";
+const NETDEV_ATTRIBUTES_ONLY: &str =
+    "--device-name, --link, --rx and --tx only apply to the netdev trigger";
+const HOOK_MISSING_PERIODS: &str = "expected the old and new periods";
+
+/// The duration of ramps in `auto`, if neither an argument nor the configuration gives one.
+const DEFAULT_AUTO_DURATION: Duration = Duration::from_secs(1);
+
+/// The duration of fades in `power --fade`, if neither an argument nor the configuration gives one.
+const DEFAULT_FADE_DURATION: Duration = Duration::from_millis(500);
+
+/// The period of `blink` and `pulse`, if no argument gives one.
+const DEFAULT_EFFECT_PERIOD: Duration = Duration::from_secs(1);
+
+/// The signals that stop `blink` and `pulse`, after which the brightness is restored.
+const TERMINATION_SIGNALS: &[libc::c_int; 2] = &[SIGINT, SIGTERM];
+
+/// The environment variables that change what a command does,
+/// which are sent to the daemon along with the arguments.
+const FORWARDED_VARS: [&str; 4] = [SYSFS_ROOT_ENV, "XDG_CONFIG_HOME", "XDG_STATE_HOME", "HOME"];
+
+/// The environment variables of the invocation that a command came from,
+/// which are those of the client rather than the daemon.
+#[derive(Debug, Default)]
+pub struct Environment(HashMap<String, OsString>);
+
+impl Environment {
+    /// The variables of this process that are among [`FORWARDED_VARS`].
+    fn current() -> Self {
+        Self(
+            FORWARDED_VARS
+                .iter()
+                .filter_map(|&name| Some((name.to_owned(), std::env::var_os(name)?)))
+                .collect(),
+        )
+    }
+
+    fn var(&self, name: &str) -> Option<OsString> {
+        self.0.get(name).cloned()
+    }
+}
+
+/// Where the output of a command is written, either
+/// the terminal or the client of the daemon.
+pub struct Output {
+    pub stdout: Box<dyn Write + Send>,
+    pub stderr: Box<dyn Write + Send>,
+}
+
+impl Output {
+    fn stdio() -> Self {
+        Self {
+            stdout: Box::new(std::io::stdout()),
+            stderr: Box::new(std::io::stderr()),
+        }
+    }
+}
+
+macro_rules! outln {
+    ($output:expr, $($arg:tt)*) => {
+        writeln!($output.stdout, $($arg)*).map_err(Error::OutputFailed)
+    };
+}
+
+macro_rules! errln {
+    ($output:expr, $($arg:tt)*) => {
+        writeln!($output.stderr, $($arg)*).map_err(Error::OutputFailed)
+    };
+}
+
+/// State that outlives a single command, which is shared
+/// by every client when running as a daemon.
+pub struct Context {
+    /// Devices that have been opened, which are only kept when running as a daemon.
+    devices: Option<Mutex<HashMap<(PathBuf, bool), SharedDevice>>>,
+    ramps: RampTracker,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self {
+            devices: None,
+            ramps: RampTracker::with_device_locks(),
+        }
+    }
+}
+
+impl Context {
+    /// A context which keeps devices open between commands.
+    fn persistent() -> Self {
+        Self {
+            devices: Some(Mutex::default()),
+            ..Self::default()
+        }
+    }
+
+    fn open_device(&self, detail: &DeviceDetail, logind: bool) -> slight::Result<BoxedDevice> {
+        let Some(devices) = &self.devices else {
+            return open_device(detail, logind);
+        };
+        let mut devices = devices.lock().unwrap_or_else(PoisonError::into_inner);
+        let key = (detail.path.clone(), logind);
+        let device = match devices.get(&key) {
+            Some(device) if !device.failed.load(Ordering::Relaxed) => device.clone(),
+            _ => {
+                let device = SharedDevice::new(open_device(detail, logind)?);
+                devices.insert(key, device.clone());
+                device
+            }
+        };
+        Ok(Box::new(device))
+    }
+}
+
+/// A device that can be used from many threads, for one operation at a time.
+#[derive(Clone)]
+struct SharedDevice {
+    device: Arc<Mutex<BoxedDevice>>,
+    /// Whether an operation failed, such as after the device was unplugged,
+    /// so that it is opened again for the next command.
+    failed: Arc<AtomicBool>,
+}
+
+impl SharedDevice {
+    fn new(device: BoxedDevice) -> Self {
+        Self {
+            device: Arc::new(Mutex::new(device)),
+            failed: Arc::default(),
+        }
+    }
+
+    fn with<T, E>(
+        &self,
+        f: impl FnOnce(&BoxedDevice) -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
+        let result = f(&self.device.lock().unwrap_or_else(PoisonError::into_inner));
+        if result.is_err() {
+            self.failed.store(true, Ordering::Relaxed);
+        }
+        result
+    }
+}
+
+impl Brightness for SharedDevice {
+    fn brightness(&self) -> ReadNumResult<u32> {
+        self.with(|device| device.brightness())
+    }
+
+    fn set_brightness(&self, value: u32) -> WriteResult {
+        self.with(|device| device.set_brightness(value))
+    }
+
+    fn max_brightness(&self) -> ReadNumResult<u32> {
+        self.with(|device| device.max_brightness())
+    }
 
-const DEFAULT_DEVICE_PATHS: &[&str; 2] = &["/sys/class/backlight", "/sys/class/leds"];
+    fn brightness_hw_changed(&self) -> Option<u32> {
+        self.device
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .brightness_hw_changed()
+    }
+
+    fn is_slow_to_read(&self) -> bool {
+        self.device
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_slow_to_read()
+    }
+}
 
 fn main() -> Result<()> {
     let args = slight_command().run();
+    let env = Environment::current();
 
-    let found_devices = Lazy::<Vec<DeviceDetail>>::new(find_devices);
-
-    fn default_device(found: Lazy<Vec<DeviceDetail>>) -> Result<DeviceDetail> {
-        Lazy::force(&found);
-        let defaults = Lazy::into_value(found).unwrap();
-        defaults.into_iter().next().ok_or(Error::NoDefaultDevice)
+    match args.command {
+        Action::Daemon => daemon::serve(),
+        // the daemon would not receive the signals that stop these
+        Action::Blink { .. }
+        | Action::Pulse { .. }
+        | Action::Pattern { .. }
+        | Action::Auto { .. } => execute(args, &Context::default(), &env, &mut Output::stdio()),
+        _ if !args.no_daemon => match daemon::connect() {
+            Some(stream) => daemon::forward(stream, &env, std::env::args_os().skip(1)),
+            None => execute(args, &Context::default(), &env, &mut Output::stdio()),
+        },
+        _ => execute(args, &Context::default(), &env, &mut Output::stdio()),
     }
+}
 
-    let verbose = args.verbose;
+/// Run a command, either directly or on behalf of a client of the daemon.
+fn execute(
+    args: SlightCommand,
+    ctx: &Context,
+    env: &Environment,
+    output: &mut Output,
+) -> Result<()> {
+    let sysfs = args
+        .sysfs_root
+        .clone()
+        .or_else(|| env.var(SYSFS_ROOT_ENV).map(PathBuf::from))
+        .map_or_else(Sysfs::default, Sysfs::new);
+    let found_devices = FoundDevices::new(sysfs);
+    let config = Config::load_with(|name| env.var(name)).map_err(slight::Error::ConfigFailed)?;
 
     match args.command {
-        Action::List { paths } => {
+        Action::List { json: true, .. } => {
+            let defaults = default_paths(&config, &found_devices);
+            let statuses = found_devices
+                .iter()
+                .map(|detail| DeviceStatus {
+                    default: defaults.contains(&detail.path),
+                    ..device_status(ctx, detail, &args, &config)
+                })
+                .collect::<Vec<_>>();
+            print_json(output, &statuses)
+        }
+        Action::List { paths: true, .. } => {
+            // only the paths, so that they can be used by other commands
             for device in found_devices.iter() {
-                if paths {
-                    println!("{}", device.path.display());
+                outln!(output, "{}", device.path.display())?;
+            }
+            Ok(())
+        }
+        Action::List { .. } => {
+            let defaults = default_paths(&config, &found_devices);
+            for device in found_devices.iter() {
+                let marker = if defaults.contains(&device.path) {
+                    " (default)"
                 } else {
-                    println!("{}", device.name);
-                }
+                    ""
+                };
+                outln!(output, "{}{marker}", device.name)?;
             }
             Ok(())
         }
-        Action::Get { percent } => {
-            let device = args.device.unwrap_or(default_device(found_devices)?.path);
-            let device = LedDevice::new(device);
-            let current = read_brightness(&device)?;
-            let current = Value::Absolute(current);
-            if percent {
-                let max = read_max_brightness(&device)?;
-                let actual = current.as_percent(max);
-                println!("{actual}");
-            } else {
-                println!("{current}");
+        Action::Get { json: true, .. } => {
+            let defaults = default_paths(&config, &found_devices);
+            let statuses = select_devices(&args.device, &config, &found_devices)?
+                .iter()
+                .map(|detail| DeviceStatus {
+                    default: defaults.contains(&detail.path),
+                    ..device_status(ctx, detail, &args, &config)
+                })
+                .collect::<Vec<_>>();
+            print_json(output, &statuses)
+        }
+        Action::Get { percent, .. } => {
+            let devices = select_devices(&args.device, &config, &found_devices)?;
+            let labeled = devices.len() > 1;
+            for detail in devices {
+                let device = ctx.open_device(&detail, args.logind)?;
+                let curve = config.curve_for(&args.curve, &detail);
+                let current = read_brightness(&*device)?;
+                let current = Value::Absolute(current);
+                let current = if percent {
+                    let max = read_max_brightness(&*device)?;
+                    current.as_percent(max, curve)
+                } else {
+                    current
+                };
+                if labeled {
+                    outln!(output, "{}: {current}", detail.name)?;
+                } else {
+                    outln!(output, "{current}")?;
+                }
             }
             Ok(())
         }
@@ -76,161 +324,692 @@ fn main() -> Result<()> {
             value,
             increase,
             decrease,
-            interpolate:
-                InterpolationOptions {
-                    duration,
-                    frequency,
-                },
+            interpolate,
         } => {
             if increase && decrease {
                 return Err(Error::MalformedArguments(CONFLICT_INCREASE_DECREASE.into()));
             }
 
-            let device = args.device.unwrap_or(default_device(found_devices)?.path);
-            let device = LedDevice::new(device);
-            let max = read_max_brightness(&device)?;
-            let current = read_brightness(&device)?;
-            let target = value.to_absolute(max);
-
-            if target == current {
-                Ok(())
-            } else if increase && target < current {
-                eprintln!("{CURRENT_BRIGHTNESS_GREATER}");
-                Ok(())
-            } else if decrease && target > current {
-                eprintln!("{CURRENT_BRIGHTNESS_LESS}");
-                Ok(())
-            } else {
-                set_brightness(&device, current, target, duration.0, frequency, max)
+            let mut transitions = Vec::new();
+            for detail in select_devices(&args.device, &config, &found_devices)? {
+                let device = ctx.open_device(&detail, args.logind)?;
+                let curve = config.curve_for(&args.curve, &detail);
+                let max = read_max_brightness(&*device)?;
+                let current = read_brightness(&*device)?;
+                let target = config.clamp(&detail, value.to_absolute(max, curve), max, curve);
+
+                if target == current {
+                    continue;
+                } else if increase && target < current {
+                    errln!(output, "{}: {CURRENT_BRIGHTNESS_GREATER}", detail.name)?;
+                } else if decrease && target > current {
+                    errln!(output, "{}: {CURRENT_BRIGHTNESS_LESS}", detail.name)?;
+                } else {
+                    transitions.push(Transition {
+                        path: detail.path,
+                        device,
+                        target,
+                        curve: curve.clone(),
+                        portion: portion(current, target, Value::Percent(1.0), max, curve),
+                    });
+                }
             }
+
+            Ok(set_brightness_all(
+                transitions,
+                &interpolate.or_config(&config),
+                &ctx.ramps,
+                &SystemClock,
+            )?)
         }
         Action::Increase {
             amount,
-            interpolate:
-                InterpolationOptions {
-                    duration,
-                    frequency,
-                },
+            interpolate,
         } => {
-            let device = args.device.unwrap_or(default_device(found_devices)?.path);
-            let device = LedDevice::new(device);
-            let max = read_max_brightness(&device)?;
-            let amount = amount.to_absolute(max);
-            let current = read_brightness(&device)?;
-            let target = (current + amount).clamp(0, max);
+            let mut transitions = Vec::new();
+            for detail in select_devices(&args.device, &config, &found_devices)? {
+                let device = ctx.open_device(&detail, args.logind)?;
+                let curve = config.curve_for(&args.curve, &detail);
+                let max = read_max_brightness(&*device)?;
+                let current = read_brightness(&*device)?;
+                let target = Value::saturating_add(current, amount, max, curve);
+                let target = config.clamp(&detail, target, max, curve);
 
-            set_brightness(&device, current, target, duration.0, frequency, amount)
+                transitions.push(Transition {
+                    path: detail.path,
+                    device,
+                    target,
+                    curve: curve.clone(),
+                    portion: portion(current, target, amount, max, curve),
+                });
+            }
+
+            Ok(set_brightness_all(
+                transitions,
+                &interpolate.or_config(&config),
+                &ctx.ramps,
+                &SystemClock,
+            )?)
         }
         Action::Decrease {
             amount,
-            interpolate:
-                InterpolationOptions {
-                    duration,
-                    frequency,
+            interpolate,
+        } => {
+            let mut transitions = Vec::new();
+            for detail in select_devices(&args.device, &config, &found_devices)? {
+                let device = ctx.open_device(&detail, args.logind)?;
+                let curve = config.curve_for(&args.curve, &detail);
+                let max = read_max_brightness(&*device)?;
+                let current = read_brightness(&*device)?;
+                let target = Value::saturating_sub(current, amount, max, curve);
+                let target = config.clamp(&detail, target, max, curve);
+
+                transitions.push(Transition {
+                    path: detail.path,
+                    device,
+                    target,
+                    curve: curve.clone(),
+                    portion: portion(current, target, amount, max, curve),
+                });
+            }
+
+            Ok(set_brightness_all(
+                transitions,
+                &interpolate.or_config(&config),
+                &ctx.ramps,
+                &SystemClock,
+            )?)
+        }
+        Action::Save { all, file } => {
+            let path = file.unwrap_or_else(|| state_path_with(|name| env.var(name)));
+            let mut state = SavedState::load(&path).map_err(slight::Error::StateFailed)?;
+            let devices = if all {
+                found_devices.to_vec()
+            } else {
+                select_devices(&args.device, &config, &found_devices)?
+            };
+            for detail in devices {
+                let device = ctx.open_device(&detail, args.logind)?;
+                let bl_power = match detail.capability {
+                    Capability::Backlight => {
+                        Some(read_bl_power(&BacklightDevice::new(detail.path.clone()))?)
+                    }
+                    _ => None,
+                };
+                state.insert(SavedDevice {
+                    brightness: read_brightness(&*device)?,
+                    name: detail.name,
+                    path: detail.path,
+                    bl_power,
+                });
+            }
+            Ok(state.store(&path).map_err(slight::Error::StateFailed)?)
+        }
+        Action::Restore {
+            all,
+            file,
+            interpolate,
+        } => {
+            let path = file.unwrap_or_else(|| state_path_with(|name| env.var(name)));
+            let state = SavedState::load(&path).map_err(slight::Error::StateFailed)?;
+            let devices = if all {
+                let mut devices = Vec::new();
+                for saved in &state.devices {
+                    match DeviceDetail::try_from(saved.path.clone()) {
+                        Ok(detail) => devices.push(detail),
+                        Err(()) => errln!(output, "{}: {DEVICE_NO_LONGER_EXISTS}", saved.name)?,
+                    }
+                }
+                devices
+            } else {
+                select_devices(&args.device, &config, &found_devices)?
+            };
+
+            let mut transitions = Vec::new();
+            // backlights are blanked after their brightness is restored
+            let mut blank = Vec::new();
+            for detail in devices {
+                let saved = state
+                    .get(&detail.path)
+                    .ok_or_else(|| slight::Error::NoSavedState(detail.name.clone()))?;
+                if let Some(bl_power) = saved.bl_power {
+                    let backlight = BacklightDevice::new(detail.path.clone());
+                    if !bl_power.is_on() {
+                        blank.push((backlight, bl_power));
+                    } else if read_bl_power(&backlight)? != bl_power {
+                        write_bl_power(&backlight, bl_power)?;
+                    }
+                }
+
+                let device = ctx.open_device(&detail, args.logind)?;
+                let curve = config.curve_for(&args.curve, &detail);
+                let max = read_max_brightness(&*device)?;
+                let current = read_brightness(&*device)?;
+                let target = saved.brightness.min(max);
+                if target != current {
+                    transitions.push(Transition {
+                        path: detail.path,
+                        device,
+                        target,
+                        curve: curve.clone(),
+                        portion: portion(current, target, Value::Percent(1.0), max, curve),
+                    });
+                }
+            }
+
+            set_brightness_all(
+                transitions,
+                &interpolate.or_config(&config),
+                &ctx.ramps,
+                &SystemClock,
+            )?;
+            for (backlight, bl_power) in &blank {
+                write_bl_power(backlight, *bl_power)?;
+            }
+            Ok(())
+        }
+        Action::Power {
+            state: PowerCommand::Status,
+            ..
+        } => {
+            let devices = select_backlights(&args.device, &config, &found_devices)?;
+            let labeled = devices.len() > 1;
+            for detail in devices {
+                let bl_power = read_bl_power(&BacklightDevice::new(detail.path))?;
+                let status = match bl_power {
+                    PowerState::Unblank => "on",
+                    PowerState::Powerdown => "off",
+                    PowerState::Normal => "off (normal)",
+                    PowerState::VsyncSuspend => "off (vsync_suspend)",
+                    PowerState::HsyncSuspend => "off (hsync_suspend)",
+                };
+                if labeled {
+                    outln!(output, "{}: {status}", detail.name)?;
+                } else {
+                    outln!(output, "{status}")?;
+                }
+            }
+            Ok(())
+        }
+        Action::Power {
+            fade,
+            interpolate,
+            state,
+        } => {
+            let mut interpolate = interpolate.or_config(&config);
+            interpolate
+                .duration
+                .get_or_insert(DurationArgument(DEFAULT_FADE_DURATION));
+
+            let mut fade_out = Vec::new();
+            let mut fade_in = Vec::new();
+            let mut power_off = Vec::new();
+            for detail in select_backlights(&args.device, &config, &found_devices)? {
+                let backlight = BacklightDevice::new(detail.path.clone());
+                let is_on = read_bl_power(&backlight)?.is_on();
+                let turn_on = match state {
+                    PowerCommand::On => true,
+                    PowerCommand::Off => false,
+                    PowerCommand::Toggle => !is_on,
+                    PowerCommand::Status => unreachable!("handled above"),
+                };
+                if turn_on == is_on {
+                    continue;
+                }
+
+                let device = ctx.open_device(&detail, args.logind)?;
+                let brightness = read_brightness(&*device)?;
+                let transition = |device, target| Transition {
+                    path: detail.path.clone(),
+                    device,
+                    target,
+                    curve: config.curve_for(&args.curve, &detail).clone(),
+                    portion: 1.0,
+                };
+                if turn_on {
+                    if fade {
+                        write_brightness(&*device, 0)?;
+                        fade_in.push(transition(device, brightness));
+                    }
+                    write_bl_power(&backlight, PowerState::Unblank)?;
+                } else {
+                    if fade {
+                        let fading = ctx.open_device(&detail, args.logind)?;
+                        fade_out.push(transition(fading, 0));
+                    }
+                    // the brightness is restored once the backlight is off,
+                    // so that it is the same when it is turned on again
+                    power_off.push((backlight, device, brightness));
+                }
+            }
+
+            set_brightness_all(fade_out, &interpolate, &ctx.ramps, &SystemClock)?;
+            for (backlight, device, brightness) in power_off {
+                write_bl_power(&backlight, PowerState::Powerdown)?;
+                if fade {
+                    write_brightness(&*device, brightness)?;
+                }
+            }
+            Ok(set_brightness_all(
+                fade_in,
+                &interpolate,
+                &ctx.ramps,
+                &SystemClock,
+            )?)
+        }
+        Action::Color {
+            brightness,
+            interpolate,
+            color,
+        } => {
+            let mut transitions = Vec::new();
+            for detail in select_devices(&args.device, &config, &found_devices)? {
+                let Capability::Multicolor = detail.capability else {
+                    return Err(slight::Error::NotMulticolor(detail.name).into());
+                };
+                let led = LedDevice::new(detail.path.clone());
+                let index = led
+                    .multi_index()
+                    .map_err(|e| slight::Error::DeviceReadFailed("multi_index", e.into()))?;
+                let current_intensity = led
+                    .multi_intensity()
+                    .map_err(|e| slight::Error::DeviceReadFailed("multi_intensity", e.into()))?;
+
+                let device = ctx.open_device(&detail, args.logind)?;
+                let curve = config.curve_for(&args.curve, &detail);
+                let max = read_max_brightness(&*device)?;
+                let current = read_brightness(&*device)?;
+                let target = match brightness {
+                    Some(value) => config.clamp(&detail, value.to_absolute(max, curve), max, curve),
+                    None => current,
+                };
+                let intensity = color
+                    .intensities(&index, &current_intensity, max)
+                    .map_err(|e| slight::Error::InvalidColor(detail.name.clone(), e))?;
+
+                let fade = ColorFade::new(
+                    led,
+                    device,
+                    (current_intensity, intensity),
+                    (current, target),
+                );
+                transitions.push(Transition {
+                    path: detail.path,
+                    target: fade.target(),
+                    device: Box::new(fade),
+                    curve: Curve::Linear,
+                    portion: 1.0,
+                });
+            }
+
+            Ok(set_brightness_all(
+                transitions,
+                &interpolate.or_config(&config),
+                &ctx.ramps,
+                &SystemClock,
+            )?)
+        }
+        Action::Blink { ref effect } => {
+            let runs = effect_runs(Effect::blink, effect, &args, ctx, &config, &found_devices)?;
+            run_until_interrupted(runs, &InterpolationOptions::default(), ctx)
+        }
+        Action::Pulse {
+            ref effect,
+            frequency,
+            easing,
+        } => {
+            let runs = effect_runs(Effect::pulse, effect, &args, ctx, &config, &found_devices)?;
+            let interpolate = InterpolationOptions {
+                frequency: frequency.or(config.frequency),
+                easing,
+                ..InterpolationOptions::default()
+            };
+            run_until_interrupted(runs, &interpolate, ctx)
+        }
+        Action::Pattern {
+            fade,
+            cycles,
+            ref steps,
+        } => {
+            let mut runs = Vec::new();
+            for (detail, led) in select_leds(&args.device, &config, &found_devices)? {
+                let curve = config.curve_for(&args.curve, &detail);
+                let max = read_max_brightness(&led)?;
+                let steps = steps
+                    .iter()
+                    .map(|step| {
+                        let value = step.value.to_absolute(max, curve);
+                        (config.clamp(&detail, value, max, curve), *step.duration)
+                    })
+                    .collect::<Vec<_>>();
+                let effect = Effect::pattern(&steps, fade, cycles);
+
+                if effect.run_pattern_trigger(&led)? {
+                    outln!(output, "{}: running the pattern in the kernel", detail.name)?;
+                } else {
+                    outln!(output, "{}: running the pattern in software", detail.name)?;
+                    runs.push(EffectRun {
+                        device: ctx.open_device(&detail, args.logind)?,
+                        detail,
+                        // fade linearly, as the kernel does
+                        curve: &Curve::Linear,
+                        effect,
+                    });
+                }
+            }
+            output.stdout.flush().map_err(Error::OutputFailed)?;
+            let interpolate = InterpolationOptions {
+                frequency: config.frequency,
+                ..InterpolationOptions::default()
+            };
+            run_until_interrupted(runs, &interpolate, ctx)
+        }
+        Action::Trigger {
+            command: TriggerCommand::List,
+        } => {
+            let devices = select_leds(&args.device, &config, &found_devices)?;
+            let labeled = devices.len() > 1;
+            for (detail, led) in devices {
+                let triggers = read_triggers(&led)?;
+                for trigger in &triggers.available {
+                    let marker = if triggers.current.as_ref() == Some(trigger) {
+                        " (current)"
+                    } else {
+                        ""
+                    };
+                    if labeled {
+                        outln!(output, "{}: {trigger}{marker}", detail.name)?;
+                    } else {
+                        outln!(output, "{trigger}{marker}")?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        Action::Trigger {
+            command: TriggerCommand::Get,
+        } => {
+            let devices = select_leds(&args.device, &config, &found_devices)?;
+            let labeled = devices.len() > 1;
+            for (detail, led) in devices {
+                let trigger = read_triggers(&led)?.current.unwrap_or_default();
+                let read_failed =
+                    |e: ReadNumError| slight::Error::DeviceReadFailed("trigger", e.into());
+                let attributes = match trigger.as_str() {
+                    TIMER_TRIGGER => {
+                        let timer = led.timer().map_err(read_failed)?;
+                        format!(" delay_on={} delay_off={}", timer.delay_on, timer.delay_off)
+                    }
+                    NETDEV_TRIGGER => {
+                        let netdev = led.netdev().map_err(read_failed)?;
+                        format!(
+                            " device_name={} link={} rx={} tx={}",
+                            netdev.device_name, netdev.link as u8, netdev.rx as u8, netdev.tx as u8
+                        )
+                    }
+                    _ => String::new(),
+                };
+                if labeled {
+                    outln!(output, "{}: {trigger}{attributes}", detail.name)?;
+                } else {
+                    outln!(output, "{trigger}{attributes}")?;
+                }
+            }
+            Ok(())
+        }
+        Action::Trigger {
+            command:
+                TriggerCommand::Set {
+                    delay_on,
+                    delay_off,
+                    device_name,
+                    link,
+                    rx,
+                    tx,
+                    trigger,
                 },
         } => {
-            let device = args.device.unwrap_or(default_device(found_devices)?.path);
-            let device = LedDevice::new(device);
-            let max = read_max_brightness(&device)?;
-            let amount = amount.to_absolute(max);
-            let current = read_brightness(&device)?;
-            let target = (current - amount).clamp(0, max);
+            let timer = delay_on.is_some() || delay_off.is_some();
+            let netdev = device_name.is_some() || link.is_some() || rx.is_some() || tx.is_some();
+            if timer && trigger != TIMER_TRIGGER {
+                return Err(Error::MalformedArguments(TIMER_ATTRIBUTES_ONLY.into()));
+            }
+            if netdev && trigger != NETDEV_TRIGGER {
+                return Err(Error::MalformedArguments(NETDEV_ATTRIBUTES_ONLY.into()));
+            }
 
-            set_brightness(&device, current, target, duration.0, frequency, amount)
+            for (detail, led) in select_leds(&args.device, &config, &found_devices)? {
+                if !read_triggers(&led)?.available.contains(&trigger) {
+                    return Err(slight::Error::UnknownTrigger(trigger, detail.name).into());
+                }
+                let read_failed =
+                    |e: ReadNumError| slight::Error::DeviceReadFailed("trigger", e.into());
+                let write_failed = |e| slight::Error::DeviceWriteFailed("trigger", e);
+                led.set_trigger(&trigger).map_err(write_failed)?;
+                if timer {
+                    let current = led.timer().map_err(read_failed)?;
+                    led.set_timer(TimerTrigger {
+                        delay_on: delay_on.unwrap_or(current.delay_on),
+                        delay_off: delay_off.unwrap_or(current.delay_off),
+                    })
+                    .map_err(write_failed)?;
+                }
+                if netdev {
+                    let current = led.netdev().map_err(read_failed)?;
+                    led.set_netdev(&NetdevTrigger {
+                        device_name: device_name.clone().unwrap_or(current.device_name),
+                        link: link.unwrap_or(current.link),
+                        rx: rx.unwrap_or(current.rx),
+                        tx: tx.unwrap_or(current.tx),
+                    })
+                    .map_err(write_failed)?;
+                }
+            }
+            Ok(())
         }
-    }
-}
+        Action::Auto {
+            sensor,
+            interval,
+            once,
+            interpolate,
+        } => {
+            let settings = &config.auto;
+            let sensor = match sensor.as_ref().or(settings.sensor.as_ref()) {
+                Some(path) => LightSensor::new(path),
+                None => LightSensor::find(found_devices.sysfs()),
+            }
+            .ok_or(slight::Error::NoLightSensor)?;
+            let interval = interval.unwrap_or_else(|| settings.interval.clone());
+            let mut interpolate = interpolate.or_config(&config);
+            interpolate
+                .duration
+                .get_or_insert(DurationArgument(DEFAULT_AUTO_DURATION));
+            let devices = select_devices(&args.device, &config, &found_devices)?;
 
-fn find_devices() -> Vec<DeviceDetail> {
-    DEFAULT_DEVICE_PATHS
-        .iter()
-        .flat_map(|path| Path::new(path).read_dir())
-        .flatten()
-        .filter_map(|res| res.ok())
-        .filter_map(|entry| DeviceDetail::try_from(entry.path()).ok())
-        .collect()
-}
+            let mut smoother = Smoother::new(settings.smoothing);
+            // the brightness last chosen for each device, which is not
+            // chosen again until the light changes, so that manual
+            // adjustments are kept in the meantime
+            let mut chosen = HashMap::<PathBuf, u32>::new();
+            loop {
+                let lux = sensor
+                    .illuminance()
+                    .map_err(|e| slight::Error::DeviceReadFailed("in_illuminance", e.into()))?;
+                let value = settings.curve.to_value(smoother.update(lux));
 
-fn read_brightness(device: &dyn Brightness) -> Result<u32> {
-    device
-        .brightness()
-        .map_err(|e| Error::DeviceReadFailed("brightness", e.into()))
+                let mut transitions = Vec::new();
+                for detail in &devices {
+                    let device = ctx.open_device(detail, args.logind)?;
+                    let curve = config.curve_for(&args.curve, detail);
+                    let max = read_max_brightness(&*device)?;
+                    let target = config.clamp(detail, value.to_absolute(max, curve), max, curve);
+                    if chosen.insert(detail.path.clone(), target) == Some(target)
+                        || read_brightness(&*device)? == target
+                    {
+                        continue;
+                    }
+                    transitions.push(Transition {
+                        path: detail.path.clone(),
+                        device,
+                        target,
+                        curve: curve.clone(),
+                        portion: 1.0,
+                    });
+                }
+                set_brightness_all(transitions, &interpolate, &ctx.ramps, &SystemClock)?;
+
+                if once {
+                    return Ok(());
+                }
+                std::thread::sleep(*interval);
+            }
+        }
+        Action::Daemon => Err(Error::MalformedArguments(DAEMON_ALREADY_RUNNING.into())),
+        Action::Hook {
+            hook:
+                Hook::Gammastep {
+                    event,
+                    args: hook_args,
+                },
+        } => {
+            if event != PERIOD_CHANGED {
+                return Ok(());
+            }
+            let [old, new] = hook_args.as_slice() else {
+                return Err(Error::MalformedArguments(HOOK_MISSING_PERIODS.into()));
+            };
+            let parse_period = |period: &String| {
+                period.parse::<Period>().map_err(|_| {
+                    Error::MalformedArguments(format!("unknown period '{period}'").into())
+                })
+            };
+            let (old, new) = (parse_period(old)?, parse_period(new)?);
+
+            match hook::period_changed(&config.hook.gammastep, old, new) {
+                Some(command) => execute(SlightCommand { command, ..args }, ctx, env, output),
+                None => Ok(()),
+            }
+        }
+    }
 }
 
-fn read_max_brightness(device: &dyn Brightness) -> Result<u32> {
-    device
-        .max_brightness()
-        .map_err(|e| Error::DeviceReadFailed("max_brightness", e.into()))
+/// The state of a device, without its brightness if it cannot be opened.
+fn device_status(
+    ctx: &Context,
+    detail: &DeviceDetail,
+    args: &SlightCommand,
+    config: &Config,
+) -> DeviceStatus {
+    let mut status = DeviceStatus::from(detail);
+    if let Ok(device) = ctx.open_device(detail, args.logind) {
+        status.read_brightness(&*device, config.curve_for(&args.curve, detail));
+    }
+    status
 }
 
-fn write_brightness(device: &dyn Brightness, value: u32) -> Result<()> {
-    device
-        .set_brightness(value)
-        .map_err(|e| Error::DeviceWriteFailed("brightness", e))
+fn print_json(output: &mut Output, value: &impl serde::Serialize) -> Result<()> {
+    let json = serde_json::to_string(value).map_err(|e| Error::OutputFailed(e.into()))?;
+    outln!(output, "{json}")
 }
 
-fn set_brightness(
-    device: &dyn Brightness,
-    current: u32,
-    target: u32,
-    duration: Duration,
-    frequency: u32,
-    basis: u32,
-) -> Result<()> {
-    if duration.is_zero() {
-        write_brightness(device, target)
-    } else {
-        let delta = current.abs_diff(target);
-        let duration = if basis == 0 {
-            Duration::ZERO
-        } else {
-            duration.mul_f64(delta as f64 / basis as f64)
+/// Build an effect for every selected device, from the options of `blink` or `pulse`.
+fn effect_runs<'a>(
+    build: fn(u32, u32, Duration, Option<u32>) -> Effect,
+    options: &EffectOptions,
+    args: &'a SlightCommand,
+    ctx: &Context,
+    config: &'a Config,
+    found: &FoundDevices,
+) -> Result<Vec<EffectRun<'a>>> {
+    let period = options
+        .period
+        .as_deref()
+        .copied()
+        .unwrap_or(DEFAULT_EFFECT_PERIOD);
+    let mut runs = Vec::new();
+    for detail in select_devices(&args.device, config, found)? {
+        let device = ctx.open_device(&detail, args.logind)?;
+        let curve = config.curve_for(&args.curve, &detail);
+        let max = read_max_brightness(&*device)?;
+        let value = |value: Option<Value>, default| {
+            let value = value.map_or(default, |value| value.to_absolute(max, curve));
+            config.clamp(&detail, value, max, curve)
         };
-        if duration.is_zero() {
-            write_brightness(device, target)
-        } else {
-            ramp_brightness(device, target, duration, frequency)
-        }
+        let effect = build(
+            value(options.on, max),
+            value(options.off, 0),
+            period,
+            options.cycles,
+        );
+        runs.push(EffectRun {
+            detail,
+            device,
+            curve,
+            effect,
+        });
     }
+    Ok(runs)
 }
 
-fn ramp_brightness(
-    device: &dyn Brightness,
-    target: u32,
-    duration: Duration,
-    frequency: u32,
+/// Run every effect until they finish or the process is interrupted,
+/// see [`run_effects`].
+fn run_until_interrupted(
+    runs: Vec<EffectRun>,
+    interpolate: &InterpolationOptions,
+    ctx: &Context,
 ) -> Result<()> {
-    assert!(!duration.is_zero() && frequency > 0);
-
-    let max = read_max_brightness(device)?;
-    let start = read_brightness(device)?;
-    let target = target.min(max);
-
-    let delta = start.abs_diff(target);
-    if delta == 0 {
-        return Ok(());
-    }
-    let steps = ((duration.as_secs_f64() * frequency as f64).floor() as u32)
-        .max(1)
-        .min(delta);
-    let interval = duration / steps;
-    let mut next_update = Instant::now() + interval;
-
-    for step in 1..=steps {
-        let now = Instant::now();
-        if now < next_update {
-            std::thread::sleep(next_update - now);
-        }
-        next_update += interval;
-        let prog = (delta as u64 * step as u64 / steps as u64) as u32;
-        let value = if target >= start {
-            start + prog
-        } else {
-            start - prog
-        };
+    let interrupted = CancelToken::default();
+    let handlers = interrupted
+        .cancel_on(TERMINATION_SIGNALS)
+        .map_err(Error::SignalFailed)?;
+    let result = run_effects(runs, interpolate, &ctx.ramps, &SystemClock, &interrupted);
+    for handler in handlers {
+        signal_hook::low_level::unregister(handler);
+    }
+    Ok(result?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::atomic::Ordering;
+
+    use slight::discovery::{FoundDevices, Sysfs};
 
-        write_brightness(device, value)?;
+    use super::Context;
+
+    fn is_failed(ctx: &Context, path: &Path) -> bool {
+        let devices = ctx.devices.as_ref().unwrap().lock().unwrap();
+        devices[&(path.to_owned(), false)]
+            .failed
+            .load(Ordering::Relaxed)
     }
 
-    Ok(())
+    #[test]
+    fn test_reopen_failed_device() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("class/backlight/acpi_video0");
+        std::fs::create_dir_all(&dir).unwrap();
+        for (attribute, value) in [
+            ("brightness", "0"),
+            ("max_brightness", "255"),
+            ("type", "raw"),
+        ] {
+            std::fs::write(dir.join(attribute), value).unwrap();
+        }
+        let found = FoundDevices::new(Sysfs::new(root.path()));
+        let detail = found.iter().next().unwrap();
+        let ctx = Context::persistent();
+
+        // as if the device was unplugged
+        let device = ctx.open_device(detail, false).unwrap();
+        std::fs::remove_file(dir.join("brightness")).unwrap();
+        assert!(device.brightness().is_err());
+        assert!(is_failed(&ctx, &detail.path));
+
+        std::fs::write(dir.join("brightness"), "7").unwrap();
+        let device = ctx.open_device(detail, false).unwrap();
+        assert!(!is_failed(&ctx, &detail.path));
+        assert_eq!(device.brightness().unwrap(), 7);
+    }
 }
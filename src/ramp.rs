@@ -0,0 +1,685 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use signal_hook::SigId;
+
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::curve::Curve;
+use crate::device::Brightness;
+use crate::easing::Easing;
+use crate::lock::DeviceLock;
+use crate::value::{DurationArgument, Value};
+use crate::{read_brightness, read_max_brightness, write_brightness, BoxedDevice, Error, Result};
+
+/// The frequency of updates during a ramp, if neither an argument nor the configuration gives one.
+pub const DEFAULT_FREQUENCY: u32 = 30;
+
+/// How often a ramp reads the brightness of a device that is slow to read.
+const SLOW_READ_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How a change of brightness is interpolated over time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterpolationOptions {
+    /// Maximum duration of time over which to interpolate the change.
+    pub duration: Option<DurationArgument>,
+    /// The maximum frequency of brightness updates (Hz), [`DEFAULT_FREQUENCY`] by default.
+    pub frequency: Option<u32>,
+    /// The rate of change over the duration.
+    pub easing: Easing,
+    /// Whether to stop when the brightness is changed by something else.
+    pub external_change: ExternalChange,
+}
+
+impl Default for InterpolationOptions {
+    /// The same as when no arguments are given.
+    fn default() -> Self {
+        Self {
+            duration: None,
+            frequency: None,
+            easing: Easing::Linear,
+            external_change: ExternalChange::Yield,
+        }
+    }
+}
+
+impl InterpolationOptions {
+    /// Use the defaults from the configuration file for options that were not given.
+    pub fn or_config(self, config: &Config) -> Self {
+        Self {
+            duration: self.duration.or_else(|| config.duration.clone()),
+            frequency: self.frequency.or(config.frequency),
+            ..self
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration.as_deref().copied().unwrap_or(Duration::ZERO)
+    }
+
+    pub fn frequency(&self) -> u32 {
+        self.frequency.unwrap_or(DEFAULT_FREQUENCY)
+    }
+}
+
+/// What to do when the brightness is changed by something else during a ramp,
+/// such as a brightness key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExternalChange {
+    /// Stop the ramp when the brightness is changed externally
+    Yield,
+    /// Continue the ramp even if the brightness is changed externally
+    Force,
+}
+
+/// A pending change of brightness for a single device.
+pub struct Transition {
+    pub path: PathBuf,
+    pub device: BoxedDevice,
+    pub target: u32,
+    pub curve: Curve,
+    /// The portion of the full duration that this change should take.
+    pub portion: f64,
+}
+
+/// A flag shared with an in-flight ramp, which stops it when set.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>, Option<Arc<AtomicBool>>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+            || self
+                .1
+                .as_ref()
+                .is_some_and(|outer| outer.load(Ordering::Acquire))
+    }
+
+    /// A clone of this token that is also cancelled along with `outer`,
+    /// such as the ramp of one device when a whole command is interrupted.
+    pub fn within(&self, outer: &Self) -> Self {
+        Self(self.0.clone(), Some(outer.0.clone()))
+    }
+
+    /// Cancel when the process receives any of `signals`, instead of being
+    /// terminated, until the returned handlers are unregistered.
+    pub fn cancel_on(&self, signals: &[libc::c_int]) -> std::io::Result<Vec<SigId>> {
+        signals
+            .iter()
+            .map(|&signal| signal_hook::flag::register(signal, self.0.clone()))
+            .collect()
+    }
+
+    /// Whether both tokens belong to the same ramp.
+    pub fn same(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Held by the ramp that is running for a device.
+pub type RunningLock = Arc<Mutex<()>>;
+
+/// Keeps track of the ramp for each device, so that starting
+/// a new one stops the last instead of both writing alternately.
+///
+/// By default, this only concerns ramps within this process, see
+/// [`RampTracker::with_device_locks`] for stopping those of other processes.
+#[derive(Debug, Default)]
+pub struct RampTracker {
+    ramps: Mutex<HashMap<PathBuf, (CancelToken, RunningLock)>>,
+    lock_devices: bool,
+}
+
+impl RampTracker {
+    /// A tracker which also takes a [`DeviceLock`] for each ramp, so that
+    /// ramps of the same device in other processes are stopped too.
+    ///
+    /// The first lock taken starts a thread that handles `SIGUSR1` for
+    /// the rest of the life of the process, which is how other processes
+    /// ask for a lock to be released.
+    pub fn with_device_locks() -> Self {
+        Self {
+            ramps: Mutex::default(),
+            lock_devices: true,
+        }
+    }
+
+    /// Cancel the ramp in progress for the device at `path`, and return a
+    /// token for the next one, along with a lock to hold while it runs.
+    pub fn begin(&self, path: &Path) -> (CancelToken, RunningLock) {
+        let mut ramps = self.ramps.lock().unwrap_or_else(PoisonError::into_inner);
+        let token = CancelToken::default();
+        let (last, running) = ramps
+            .entry(path.to_owned())
+            .or_insert_with(|| (CancelToken::default(), Arc::default()));
+        last.cancel();
+        *last = token.clone();
+        (token, running.clone())
+    }
+
+    /// Take the [`DeviceLock`] of the device at `path` if this tracker takes
+    /// them, since another process may be ramping the same device.
+    pub(crate) fn lock(&self, path: &Path, cancel: &CancelToken) -> Result<Option<DeviceLock>> {
+        self.lock_devices
+            .then(|| DeviceLock::acquire(path, cancel))
+            .transpose()
+            .map_err(Error::DeviceLockFailed)
+    }
+}
+
+/// Apply every transition concurrently, scaling the duration by the largest
+/// relative change so that all devices finish at the same time.
+///
+/// Any ramps already in progress for the same devices are cancelled first,
+/// including those of other processes if `tracker` takes device locks.
+pub fn set_brightness_all(
+    transitions: Vec<Transition>,
+    interpolate: &InterpolationOptions,
+    tracker: &RampTracker,
+    clock: &dyn Clock,
+) -> Result<()> {
+    let duration = longest_duration(&transitions, interpolate);
+
+    std::thread::scope(|scope| {
+        let handles = transitions
+            .into_iter()
+            .map(|t| {
+                let (cancel, running) = tracker.begin(&t.path);
+                scope.spawn(move || {
+                    let _running = running.lock().unwrap_or_else(PoisonError::into_inner);
+                    if cancel.is_cancelled() {
+                        return Ok(());
+                    }
+                    let _lock = tracker.lock(&t.path, &cancel)?;
+                    if cancel.is_cancelled() {
+                        Ok(())
+                    } else if duration.is_zero() {
+                        write_brightness(&*t.device, t.target)
+                    } else {
+                        ramp_brightness(
+                            &*t.device,
+                            t.target,
+                            duration,
+                            interpolate,
+                            &t.curve,
+                            clock,
+                            &cancel,
+                        )
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().expect("ramp thread panicked"))
+    })
+}
+
+/// The duration of the largest change of `transitions`, which all of them take.
+pub(crate) fn longest_duration(
+    transitions: &[Transition],
+    interpolate: &InterpolationOptions,
+) -> Duration {
+    transitions
+        .iter()
+        .map(|t| interpolate.duration().mul_f64(t.portion))
+        .max()
+        .unwrap_or(Duration::ZERO)
+}
+
+/// The portion of the full duration that a change from `current` to `target`
+/// should take, where `amount` is the change corresponding to the full duration.
+pub fn portion(current: u32, target: u32, amount: Value, max: u32, curve: &Curve) -> f64 {
+    let (delta, basis) = match amount {
+        Value::Percent(pct) => (
+            (curve.to_percent(target, max) - curve.to_percent(current, max)).abs() as f64,
+            pct as f64,
+        ),
+        Value::Absolute(abs) => (current.abs_diff(target) as f64, abs as f64),
+    };
+    if basis <= 0.0 {
+        0.0
+    } else {
+        (delta / basis).min(1.0)
+    }
+}
+
+/// Interpolate from the current brightness to `target` over `duration`,
+/// easing the perceived brightness along `curve`, until cancelled.
+/// The time of each step is taken from `clock`, usually [`SystemClock`].
+///
+/// See [`Ramp`] for when the ramp stops early.
+///
+/// [`SystemClock`]: crate::clock::SystemClock
+pub fn ramp_brightness(
+    device: &dyn Brightness,
+    target: u32,
+    duration: Duration,
+    interpolate: &InterpolationOptions,
+    curve: &Curve,
+    clock: &dyn Clock,
+    cancel: &CancelToken,
+) -> Result<()> {
+    let ramp = Ramp::start(device, target, duration, interpolate, curve, clock.now())?;
+    match ramp {
+        Some(ramp) => ramp.run(device, clock, cancel),
+        None => Ok(()),
+    }
+}
+
+/// A ramp of one device that is in progress, which is advanced one step
+/// at a time, so that a single loop can drive the ramps of many devices.
+///
+/// Unless forced, the ramp stops when the brightness is changed by something
+/// else, which is detected by the device reporting a hardware change, or by
+/// reading a value more than a percent away from the one read after the last
+/// write. Devices that are slow to read, such as over DDC/CI, are only read
+/// about once a second, and compared with the value last written instead.
+#[derive(Debug, Clone)]
+pub struct Ramp {
+    target: u32,
+    max: u32,
+    start_pct: f64,
+    target_pct: f64,
+    curve: Curve,
+    easing: Easing,
+    steps: u32,
+    step: u32,
+    interval: Duration,
+    next_update: Instant,
+    last: u32,
+    watch: bool,
+    /// Read the brightness to check for changes only every this many steps.
+    read_every: u32,
+    /// Whether to read back each written value, which the device may round.
+    read_back: bool,
+    hw_changed: Option<u32>,
+    last_seen: u32,
+}
+
+impl Ramp {
+    /// Begin a ramp from the current brightness to `target`, with the first
+    /// update one interval after `now`, or `None` if there is nothing to change.
+    pub fn start(
+        device: &dyn Brightness,
+        target: u32,
+        duration: Duration,
+        interpolate: &InterpolationOptions,
+        curve: &Curve,
+        now: Instant,
+    ) -> Result<Option<Self>> {
+        let frequency = interpolate.frequency();
+        assert!(!duration.is_zero() && frequency > 0);
+
+        let max = read_max_brightness(device)?;
+        let start = read_brightness(device)?;
+        let target = target.min(max);
+
+        if start == target {
+            return Ok(None);
+        }
+        let steps = ((duration.as_secs_f64() * frequency as f64).floor() as u32).max(1);
+        let interval = duration / steps;
+        let slow = device.is_slow_to_read();
+        let read_every = if slow {
+            SLOW_READ_INTERVAL.as_nanos().div_ceil(interval.as_nanos()) as u32
+        } else {
+            1
+        };
+        Ok(Some(Self {
+            target,
+            max,
+            start_pct: curve.to_percent(start, max) as f64,
+            target_pct: curve.to_percent(target, max) as f64,
+            curve: curve.clone(),
+            easing: interpolate.easing,
+            steps,
+            step: 0,
+            interval,
+            next_update: now + interval,
+            last: start,
+            watch: interpolate.external_change == ExternalChange::Yield,
+            read_every: read_every.max(1),
+            read_back: !slow,
+            hw_changed: device.brightness_hw_changed(),
+            last_seen: start,
+        }))
+    }
+
+    /// When the next step is due.
+    pub fn next_update(&self) -> Instant {
+        self.next_update
+    }
+
+    /// Whether the target was reached, or the ramp stopped early.
+    pub fn is_finished(&self) -> bool {
+        self.step >= self.steps
+    }
+
+    /// Write the brightness of the next step, which should be called
+    /// once it is due, returning `false` when the ramp is finished.
+    pub fn step(&mut self, device: &dyn Brightness) -> Result<bool> {
+        if self.is_finished() {
+            return Ok(false);
+        }
+        self.step += 1;
+        self.next_update += self.interval;
+        if self.watch
+            && (device.brightness_hw_changed() != self.hw_changed
+                || (self.step.is_multiple_of(self.read_every)
+                    && read_brightness(device)?.abs_diff(self.last_seen) > self.max / 100))
+        {
+            self.step = self.steps;
+            return Ok(false);
+        }
+        let value = if self.step == self.steps {
+            self.target
+        } else {
+            let prog = self.easing.apply(self.step as f64 / self.steps as f64);
+            let pct = self.start_pct + (self.target_pct - self.start_pct) * prog;
+            self.curve.to_absolute(pct as f32, self.max)
+        };
+
+        if value != self.last {
+            write_brightness(device, value)?;
+            self.last = value;
+            if self.watch {
+                self.last_seen = if self.read_back {
+                    read_brightness(device)?
+                } else {
+                    value
+                };
+            }
+        }
+        Ok(!self.is_finished())
+    }
+
+    /// Take every remaining step when it is due by `clock`, until finished or cancelled.
+    pub fn run(
+        mut self,
+        device: &dyn Brightness,
+        clock: &dyn Clock,
+        cancel: &CancelToken,
+    ) -> Result<()> {
+        loop {
+            clock.sleep_until(self.next_update);
+            if cancel.is_cancelled() || !self.step(device)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    use test_case::test_case;
+
+    use super::{
+        ramp_brightness, CancelToken, ExternalChange, InterpolationOptions, Ramp, RampTracker,
+    };
+    use crate::clock::{Clock, VirtualClock};
+    use crate::curve::Curve;
+    use crate::device::{Brightness, ReadNumResult, WriteResult};
+    use crate::easing::Easing;
+
+    /// A device whose brightness is changed by something else
+    /// shortly after a number of writes.
+    struct ContestedDevice {
+        value: Cell<u32>,
+        written: RefCell<Vec<u32>>,
+        changed_after: usize,
+        /// Reads remaining until the brightness is changed.
+        pending_reads: Cell<Option<u32>>,
+    }
+
+    impl Brightness for ContestedDevice {
+        fn brightness(&self) -> ReadNumResult<u32> {
+            match self.pending_reads.get() {
+                Some(0) => {
+                    self.value.set(3);
+                    self.pending_reads.set(None);
+                }
+                Some(n) => self.pending_reads.set(Some(n - 1)),
+                None => {}
+            }
+            Ok(self.value.get())
+        }
+
+        fn set_brightness(&self, value: u32) -> WriteResult {
+            let mut written = self.written.borrow_mut();
+            written.push(value);
+            self.value.set(value);
+            if written.len() == self.changed_after {
+                self.pending_reads.set(Some(1));
+            }
+            Ok(())
+        }
+
+        fn max_brightness(&self) -> ReadNumResult<u32> {
+            Ok(100)
+        }
+    }
+
+    #[test_case(ExternalChange::Yield => vec![10, 20, 30])]
+    #[test_case(ExternalChange::Force => vec![10, 20, 30, 40, 50])]
+    fn test_ramp_external_change(external_change: ExternalChange) -> Vec<u32> {
+        let device = ContestedDevice {
+            value: Cell::new(0),
+            written: RefCell::default(),
+            changed_after: 3,
+            pending_reads: Cell::new(None),
+        };
+        let interpolate = InterpolationOptions {
+            duration: None,
+            frequency: Some(100),
+            easing: Easing::Linear,
+            external_change,
+        };
+        let duration = Duration::from_millis(50);
+        let (clock, cancel) = (VirtualClock::default(), CancelToken::default());
+        ramp_brightness(
+            &device,
+            50,
+            duration,
+            &interpolate,
+            &Curve::Linear,
+            &clock,
+            &cancel,
+        )
+        .unwrap();
+        device.written.into_inner()
+    }
+
+    /// A device that rounds the brightness written down to a multiple of 5,
+    /// and counts the reads.
+    struct RoundingDevice {
+        slow: bool,
+        value: Cell<u32>,
+        reads: Cell<u32>,
+    }
+
+    impl Brightness for RoundingDevice {
+        fn brightness(&self) -> ReadNumResult<u32> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(self.value.get())
+        }
+
+        fn set_brightness(&self, value: u32) -> WriteResult {
+            self.value.set(value - value % 5);
+            Ok(())
+        }
+
+        fn max_brightness(&self) -> ReadNumResult<u32> {
+            Ok(1000)
+        }
+
+        fn is_slow_to_read(&self) -> bool {
+            self.slow
+        }
+    }
+
+    #[test_case(false => (995, 1 + 60 * 2); "read back every write")]
+    #[test_case(true => (995, 1 + 6); "slow to read")]
+    fn test_ramp_yield_reads(slow: bool) -> (u32, u32) {
+        let device = RoundingDevice {
+            slow,
+            value: Cell::new(0),
+            reads: Cell::new(0),
+        };
+        let interpolate = InterpolationOptions {
+            frequency: Some(10),
+            ..InterpolationOptions::default()
+        };
+        let (clock, cancel) = (VirtualClock::default(), CancelToken::default());
+        ramp_brightness(
+            &device,
+            999,
+            Duration::from_secs(6),
+            &interpolate,
+            &Curve::Linear,
+            &clock,
+            &cancel,
+        )
+        .unwrap();
+        (device.value.get(), device.reads.get())
+    }
+
+    #[test]
+    fn test_tracker_cancels_previous_ramp() {
+        let tracker = RampTracker::default();
+        let (first, first_lock) = tracker.begin(Path::new("a"));
+        let (other, _) = tracker.begin(Path::new("b"));
+        let (second, second_lock) = tracker.begin(Path::new("a"));
+        assert!(first.is_cancelled());
+        assert!(!other.is_cancelled());
+        assert!(!second.is_cancelled());
+        assert!(std::sync::Arc::ptr_eq(&first_lock, &second_lock));
+    }
+
+    #[test]
+    fn test_cancel_within() {
+        let (outer, token) = (CancelToken::default(), CancelToken::default());
+        let inner = token.within(&outer);
+        assert!(inner.same(&token));
+        token.cancel();
+        assert!(inner.is_cancelled() && !outer.is_cancelled());
+
+        let inner = CancelToken::default().within(&outer);
+        outer.cancel();
+        assert!(inner.is_cancelled());
+    }
+
+    /// A device that records every write, along with the time it was made.
+    struct TimedDevice<'a> {
+        clock: &'a VirtualClock,
+        max: u32,
+        value: Cell<u32>,
+        written: RefCell<Vec<(Instant, u32)>>,
+    }
+
+    impl<'a> TimedDevice<'a> {
+        fn new(clock: &'a VirtualClock, max: u32) -> Self {
+            Self {
+                clock,
+                max,
+                value: Cell::new(0),
+                written: RefCell::default(),
+            }
+        }
+    }
+
+    impl Brightness for TimedDevice<'_> {
+        fn brightness(&self) -> ReadNumResult<u32> {
+            Ok(self.value.get())
+        }
+
+        fn set_brightness(&self, value: u32) -> WriteResult {
+            self.written.borrow_mut().push((self.clock.now(), value));
+            self.value.set(value);
+            Ok(())
+        }
+
+        fn max_brightness(&self) -> ReadNumResult<u32> {
+            Ok(self.max)
+        }
+    }
+
+    #[test]
+    fn test_ramp_virtual_clock() {
+        let clock = VirtualClock::default();
+        let device = TimedDevice::new(&clock, 100_000);
+        let interpolate = InterpolationOptions::default();
+        let duration = Duration::from_secs(600);
+        let start = clock.now();
+        let ramp = Ramp::start(
+            &device,
+            90_000,
+            duration,
+            &interpolate,
+            &Curve::Linear,
+            start,
+        )
+        .unwrap()
+        .unwrap();
+        ramp.run(&device, &clock, &CancelToken::default()).unwrap();
+
+        let written = device.written.into_inner();
+        assert_eq!(written.len(), 600 * 30);
+        let interval = duration / (600 * 30);
+        let mut last = start;
+        for &(time, _) in &written {
+            assert_eq!(time - last, interval);
+            last = time;
+        }
+        assert!(written.windows(2).all(|pair| pair[0].1 < pair[1].1));
+        assert_eq!(written.last().unwrap().1, 90_000);
+        assert_eq!(clock.now() - start, interval * 600 * 30);
+    }
+
+    #[test]
+    fn test_ramps_in_one_loop() {
+        let clock = VirtualClock::default();
+        let (slow, fast) = (TimedDevice::new(&clock, 100), TimedDevice::new(&clock, 100));
+        let interpolate = InterpolationOptions {
+            frequency: Some(10),
+            ..InterpolationOptions::default()
+        };
+        let now = clock.now();
+        let mut ramps = [
+            (&slow, Duration::from_secs(2)),
+            (&fast, Duration::from_secs(1)),
+        ]
+        .map(|(device, duration)| {
+            let ramp = Ramp::start(device, 100, duration, &interpolate, &Curve::Linear, now);
+            (device, ramp.unwrap().unwrap())
+        });
+
+        // step whichever ramp is due first, until all are finished
+        while let Some((device, ramp)) = ramps
+            .iter_mut()
+            .filter(|(_, ramp)| !ramp.is_finished())
+            .min_by_key(|(_, ramp)| ramp.next_update())
+        {
+            clock.sleep_until(ramp.next_update());
+            ramp.step(*device).unwrap();
+        }
+
+        let (slow, fast) = (slow.written.into_inner(), fast.written.into_inner());
+        assert_eq!((slow.len(), fast.len()), (20, 10));
+        assert_eq!(slow.last().unwrap(), &(now + Duration::from_secs(2), 100));
+        assert_eq!(fast.last().unwrap(), &(now + Duration::from_secs(1), 100));
+        assert_eq!(fast[4], (now + Duration::from_millis(500), 50));
+        assert_eq!(slow[4], (now + Duration::from_millis(500), 25));
+    }
+}
@@ -0,0 +1,130 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::device::PowerState;
+
+const STATE_FILE: &str = "slight/state.json";
+
+#[derive(Error, Debug)]
+pub enum StateError {
+    #[error("failed to read state file '{0}': {1}")]
+    ReadFailed(PathBuf, std::io::Error),
+    #[error("malformed state file '{0}': {1}")]
+    Malformed(PathBuf, serde_json::Error),
+    #[error("failed to write state file '{0}': {1}")]
+    WriteFailed(PathBuf, std::io::Error),
+}
+
+/// Brightness saved by `slight save`, to be applied again by `slight restore`.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SavedState {
+    pub devices: Vec<SavedDevice>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedDevice {
+    pub name: String,
+    pub path: PathBuf,
+    pub brightness: u32,
+    /// The power state, for backlight devices.
+    pub bl_power: Option<PowerState>,
+}
+
+impl SavedState {
+    /// Load a state file, which is empty if it does not exist yet.
+    pub fn load(path: &Path) -> Result<Self, StateError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(StateError::ReadFailed(path.to_owned(), e)),
+        };
+        serde_json::from_str(&text).map_err(|e| StateError::Malformed(path.to_owned(), e))
+    }
+
+    pub fn store(&self, path: &Path) -> Result<(), StateError> {
+        let write_failed = |e| StateError::WriteFailed(path.to_owned(), e);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(write_failed)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| write_failed(e.into()))?;
+        std::fs::write(path, json + "\n").map_err(write_failed)
+    }
+
+    /// Save a device, replacing any state previously saved for it.
+    pub fn insert(&mut self, device: SavedDevice) {
+        match self
+            .devices
+            .iter_mut()
+            .find(|saved| saved.path == device.path)
+        {
+            Some(saved) => *saved = device,
+            None => self.devices.push(device),
+        }
+    }
+
+    pub fn get(&self, path: &Path) -> Option<&SavedDevice> {
+        self.devices.iter().find(|saved| saved.path == path)
+    }
+}
+
+/// The default state file, in `$XDG_STATE_HOME` or `~/.local/state`.
+pub fn state_path() -> PathBuf {
+    state_path_with(|name| std::env::var_os(name))
+}
+
+/// Like [`state_path`], but with environment variables looked
+/// up by `var`, such as those of another process.
+pub fn state_path_with(var: impl Fn(&str) -> Option<OsString>) -> PathBuf {
+    var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join(STATE_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{SavedDevice, SavedState};
+    use crate::device::PowerState;
+
+    fn saved(name: &str, brightness: u32) -> SavedDevice {
+        SavedDevice {
+            name: name.to_owned(),
+            path: PathBuf::from("/sys/class/backlight").join(name),
+            brightness,
+            bl_power: Some(PowerState::Unblank),
+        }
+    }
+
+    #[test]
+    fn test_state_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("slight/state.json");
+        assert_eq!(SavedState::load(&path).unwrap(), SavedState::default());
+
+        let mut state = SavedState::default();
+        state.insert(saved("intel_backlight", 100));
+        state.insert(saved("acpi_video0", 5));
+        state.insert(saved("intel_backlight", 200));
+        state.store(&path).unwrap();
+
+        let loaded = SavedState::load(&path).unwrap();
+        assert_eq!(loaded, state);
+        assert_eq!(loaded.devices.len(), 2);
+        let intel = loaded.get(&saved("intel_backlight", 0).path).unwrap();
+        assert_eq!(intel.brightness, 200);
+    }
+
+    #[test]
+    fn test_malformed_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        std::fs::write(&path, "{\"devices\": [{}]}").unwrap();
+        assert!(SavedState::load(&path).is_err());
+    }
+}
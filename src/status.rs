@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::curve::Curve;
+use crate::device::{
+    Backlight, BacklightDevice, Brightness, DeviceType, LedDevice, Multicolor, PowerState,
+};
+use crate::discovery::{Capability, DeviceDetail};
+
+/// The state of a device, as printed by `list --json` and `get --json`.
+///
+/// Attributes that cannot be read, or that the device does not have,
+/// are `null`. Fields are only ever added to this schema, never removed.
+#[derive(Debug, Serialize)]
+pub struct DeviceStatus {
+    pub name: String,
+    pub path: PathBuf,
+    pub capability: Capability,
+    /// Whether this device is used when none are given.
+    pub default: bool,
+    /// The type of a backlight device: `firmware`, `platform`, or `raw`.
+    #[serde(rename = "type")]
+    pub device_type: Option<DeviceType>,
+    pub brightness: Option<u32>,
+    pub max_brightness: Option<u32>,
+    /// The brightness reported by the hardware of a backlight device.
+    pub actual_brightness: Option<u32>,
+    /// The brightness as a percentage from 0 to 100, according to the curve.
+    pub percent: Option<f32>,
+    /// The power state of a backlight device: `unblank`, `normal`,
+    /// `vsync_suspend`, `hsync_suspend`, or `powerdown`.
+    pub bl_power: Option<PowerState>,
+    /// The names of the channels of a multicolor LED.
+    pub multi_index: Option<Vec<String>>,
+    /// The intensity of each channel of a multicolor LED.
+    pub multi_intensity: Option<Vec<u32>>,
+}
+
+impl From<&DeviceDetail> for DeviceStatus {
+    fn from(detail: &DeviceDetail) -> Self {
+        let mut status = Self {
+            name: detail.name.clone(),
+            path: detail.path.clone(),
+            capability: detail.capability,
+            default: false,
+            device_type: None,
+            brightness: None,
+            max_brightness: None,
+            actual_brightness: None,
+            percent: None,
+            bl_power: None,
+            multi_index: None,
+            multi_intensity: None,
+        };
+        if let Capability::Backlight = detail.capability {
+            let backlight = BacklightDevice::new(detail.path.clone());
+            status.device_type = backlight.device_type().ok();
+            status.actual_brightness = backlight.actual_brightness().ok();
+            status.bl_power = backlight.bl_power().ok();
+        }
+        if let Capability::Multicolor = detail.capability {
+            let led = LedDevice::new(detail.path.clone());
+            status.multi_index = led.multi_index().ok();
+            status.multi_intensity = led.multi_intensity().ok();
+        }
+        status
+    }
+}
+
+impl DeviceStatus {
+    /// Read the brightness from the opened device, with the percentage according to `curve`.
+    pub fn read_brightness(&mut self, device: &dyn Brightness, curve: &Curve) {
+        self.brightness = device.brightness().ok();
+        self.max_brightness = device.max_brightness().ok();
+        self.percent = self
+            .brightness
+            .zip(self.max_brightness)
+            .map(|(current, max)| (curve.to_percent(current, max) * 10000.0).round() / 100.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeviceStatus;
+    use crate::curve::Curve;
+    use crate::device::BacklightDevice;
+    use crate::discovery::DeviceDetail;
+
+    #[test]
+    fn test_backlight_status_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("intel_backlight");
+        std::fs::create_dir(&path).unwrap();
+        for (file, contents) in [
+            ("brightness", "250\n"),
+            ("max_brightness", "1000\n"),
+            ("actual_brightness", "249\n"),
+            ("bl_power", "0\n"),
+            ("type", "raw\n"),
+        ] {
+            std::fs::write(path.join(file), contents).unwrap();
+        }
+
+        let detail = DeviceDetail::try_from(path.clone()).unwrap();
+        let mut status = DeviceStatus::from(&detail);
+        status.read_brightness(&BacklightDevice::new(path.clone()), &Curve::Linear);
+
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            serde_json::json!({
+                "name": "intel_backlight",
+                "path": path,
+                "capability": "backlight",
+                "default": false,
+                "type": "raw",
+                "brightness": 250,
+                "max_brightness": 1000,
+                "actual_brightness": 249,
+                "percent": 25.0,
+                "bl_power": "unblank",
+                "multi_index": null,
+                "multi_intensity": null,
+            })
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use std::num::{ParseFloatError, ParseIntError};
+use std::ops::Deref;
+use std::str::FromStr;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::curve::Curve;
+
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum ParseValueError {
+    #[error("percentage '{0}' must be between 0 and 100")]
+    PercentOutOfRange(u8),
+    #[error("{0} for percentage '{1}'")]
+    ParsePercentError(ParseIntError, String),
+    #[error("{0} for absolute value '{1}'")]
+    ParseAbsoluteError(ParseIntError, String),
+}
+
+/// A brightness, either as a percentage like `50%`, which is mapped
+/// along a [`Curve`], or as an absolute value of a device like `400`.
+#[derive(PartialEq, Debug, Copy, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Value {
+    Percent(f32),
+    Absolute(u32),
+}
+
+impl Value {
+    pub fn to_percent(self, max: u32, curve: &Curve) -> f32 {
+        match self {
+            Value::Percent(pct) => pct,
+            Value::Absolute(abs) => curve.to_percent(abs, max),
+        }
+    }
+
+    pub fn to_absolute(self, max: u32, curve: &Curve) -> u32 {
+        match self {
+            Value::Percent(pct) => curve.to_absolute(pct, max),
+            Value::Absolute(abs) => abs.min(max),
+        }
+    }
+
+    pub fn as_percent(self, max: u32, curve: &Curve) -> Self {
+        Value::Percent(self.to_percent(max, curve))
+    }
+
+    pub fn as_absolute(self, max: u32, curve: &Curve) -> Self {
+        Value::Absolute(self.to_absolute(max, curve))
+    }
+
+    /// Add `rhs` to `lhs`, stepping percentages along the curve so that
+    /// equal steps are perceived equally. A non-zero step always changes
+    /// the value by at least one, unless it is already at `max`.
+    pub fn saturating_add(lhs: u32, rhs: Self, max: u32, curve: &Curve) -> u32 {
+        match rhs {
+            Value::Percent(pct) => {
+                let target = curve
+                    .to_absolute(curve.to_percent(lhs, max) + pct, max)
+                    .max(lhs);
+                if target == lhs && pct > 0.0 {
+                    lhs.saturating_add(1)
+                } else {
+                    target
+                }
+            }
+            Value::Absolute(abs) => lhs.saturating_add(abs),
+        }
+        .min(max)
+    }
+
+    /// Subtract `rhs` from `lhs`, see [`Value::saturating_add`].
+    pub fn saturating_sub(lhs: u32, rhs: Self, max: u32, curve: &Curve) -> u32 {
+        let lhs = lhs.min(max);
+        match rhs {
+            Value::Percent(pct) => {
+                let target = curve
+                    .to_absolute(curve.to_percent(lhs, max) - pct, max)
+                    .min(lhs);
+                if target == lhs && pct > 0.0 {
+                    lhs.saturating_sub(1)
+                } else {
+                    target
+                }
+            }
+            Value::Absolute(abs) => lhs.saturating_sub(abs),
+        }
+    }
+}
+
+impl FromStr for Value {
+    type Err = ParseValueError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use ParseValueError as E;
+
+        let value = value.trim();
+        if value.ends_with('%') {
+            let value = value[0..value.len() - 1]
+                .parse::<u8>()
+                .map_err(|e| E::ParsePercentError(e, value.to_string()))?;
+            if !(0..=100).contains(&value) {
+                Err(E::PercentOutOfRange(value))
+            } else {
+                Ok(Self::Percent(value as f32 / 100.0))
+            }
+        } else {
+            let value = value
+                .parse::<u32>()
+                .map_err(|e| E::ParseAbsoluteError(e, value.to_string()))?;
+            Ok(Self::Absolute(value))
+        }
+    }
+}
+
+impl TryFrom<String> for Value {
+    type Error = ParseValueError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Percent(pct) => {
+                let pct = (*pct as f64 * 10000.0).round() / 100.0;
+                write!(f, "{pct}%")
+            }
+            Value::Absolute(abs) => write!(f, "{abs}"),
+        }
+    }
+}
+
+/// A wrapper of [`Duration`] that is non-zero and implements [`FromStr`].
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct DurationArgument(pub Duration);
+
+impl DurationArgument {
+    pub const ZERO: Self = DurationArgument(Duration::ZERO);
+}
+
+impl Deref for DurationArgument {
+    type Target = Duration;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl FromStr for DurationArgument {
+    type Err = ParseDurationError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        match parse_duration(value) {
+            Ok(dur) => Ok(Self(dur)),
+            Err(ParseDurationError::MissingSuffix) => {
+                let ms = value.parse().map_err(ParseDurationError::ParseIntError)?;
+                Ok(Self(Duration::from_millis(ms)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl TryFrom<String> for DurationArgument {
+    type Error = ParseDurationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum ParseDurationError {
+    #[error("duration is missing a value")]
+    MissingNumber,
+    #[error("durations cannot be negative")]
+    NegativeNumber,
+    #[error("duration is missing a suffix")]
+    MissingSuffix,
+    #[error("unknown suffix '{0}' for duration, must be one of: `ms`, `ds`, `s`, `m`")]
+    InvalidSuffix(String),
+    #[error("{0} for duration")]
+    ParseFloatError(#[from] ParseFloatError),
+    #[error("{0} for duration")]
+    ParseIntError(#[from] ParseIntError),
+}
+
+fn parse_duration(value: &str) -> Result<Duration, ParseDurationError> {
+    use ParseDurationError as E;
+
+    if value.is_empty() {
+        return Err(E::MissingNumber);
+    }
+
+    macro_rules! parse_with_suffix {
+        ($suffix:literal, $parse_ty:ty, $map:expr) => {
+            if value.ends_with($suffix) {
+                let number = &value[0..value.len() - $suffix.len()];
+                if number.is_empty() {
+                    return Err(E::MissingNumber);
+                }
+                let number = number.parse::<$parse_ty>()?;
+                if number < 0 as $parse_ty {
+                    return Err(E::NegativeNumber);
+                } else {
+                    return Ok($map(number));
+                }
+            }
+        };
+    }
+
+    parse_with_suffix!("ms", u64, Duration::from_millis);
+    parse_with_suffix!("ds", u64, |ds| Duration::from_millis(ds * 100));
+    parse_with_suffix!("s", f64, Duration::from_secs_f64);
+    parse_with_suffix!("m", f64, |m| Duration::from_secs_f64(m * 60.0));
+
+    let number = value.trim_end_matches(|ch: char| !ch.is_numeric() && ch != '.');
+    let suffix = &value[number.len()..];
+    if suffix.is_empty() {
+        Err(E::MissingSuffix)
+    } else {
+        Err(E::InvalidSuffix(suffix.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use test_case::{test_case, test_matrix};
+
+    use super::{parse_duration, ParseDurationError};
+
+    #[test_case("100ms" => Duration::from_millis(100))]
+    #[test_case("10ds" => Duration::from_secs_f64(1.0))]
+    #[test_case("1s" => Duration::from_secs(1))]
+    #[test_case("1m" => Duration::from_secs(60))]
+    #[test_case("1.0s" => Duration::from_secs_f64(1.0))]
+    #[test_case("1.0m" => Duration::from_secs_f64(60.0))]
+    fn test_parse_duration(input: &str) -> Duration {
+        parse_duration(input).unwrap()
+    }
+
+    #[test_matrix(["ms", "ds", "s", "m"] => ParseDurationError::MissingNumber)]
+    #[test_matrix(["-1.0s", "-1.0m"] => ParseDurationError::NegativeNumber)]
+    #[test_matrix(["1.0", "1"] => ParseDurationError::MissingSuffix)]
+    #[test_matrix(["1h", "h"] => ParseDurationError::InvalidSuffix("h".to_owned()))]
+    #[test_case("100.0ms" => ParseDurationError::ParseIntError("100.0".parse::<u64>().unwrap_err()))]
+    #[test_case("10.0ms" => ParseDurationError::ParseIntError("10.0".parse::<u64>().unwrap_err()))]
+    #[test_case("-100ms" => ParseDurationError::ParseIntError("-100".parse::<u64>().unwrap_err()))]
+    #[test_case("0x01s" => ParseDurationError::ParseFloatError("0x01".parse::<f64>().unwrap_err()))]
+    #[test_case("" => ParseDurationError::MissingNumber)]
+    fn test_parse_duration_error(input: &str) -> ParseDurationError {
+        parse_duration(input).unwrap_err()
+    }
+}
@@ -0,0 +1,267 @@
+//! Runs the `slight` command against a fake sysfs, checking what it
+//! writes to the devices and what it prints.
+
+mod common;
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+use common::FakeSysfs;
+use test_case::test_case;
+
+#[test]
+fn test_list() {
+    let sysfs = FakeSysfs::new();
+    sysfs.backlight("intel_backlight");
+    sysfs.led("input3::capslock");
+    assert_eq!(
+        sysfs.run(&["list"]),
+        "intel_backlight (default)\ninput3::capslock\n"
+    );
+
+    let screen = sysfs.root().join("class/backlight/intel_backlight");
+    let kbd = sysfs.root().join("class/leds/input3::capslock");
+    assert_eq!(
+        sysfs.run(&["list", "--paths"]),
+        format!("{}\n{}\n", screen.display(), kbd.display())
+    );
+}
+
+#[test]
+fn test_get() {
+    let sysfs = FakeSysfs::new();
+    sysfs.backlight("acpi_video0").with("brightness", 51);
+    sysfs
+        .led("input3::capslock")
+        .with("brightness", 1)
+        .with("max_brightness", 1);
+    assert_eq!(sysfs.run(&["get"]), "51\n");
+    assert_eq!(sysfs.run(&["get", "--percent"]), "20%\n");
+    assert_eq!(
+        sysfs.run(&["-D", "acpi_video0", "-D", "input3::*", "get"]),
+        "acpi_video0: 51\ninput3::capslock: 1\n"
+    );
+}
+
+#[test]
+fn test_get_json() {
+    let sysfs = FakeSysfs::new();
+    sysfs
+        .backlight("acpi_video0")
+        .with("brightness", 51)
+        .with("type", "firmware");
+    let json = sysfs.run(&["get", "--json"]);
+    let status: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(status[0]["name"], "acpi_video0");
+    assert_eq!(status[0]["type"], "firmware");
+    assert_eq!(status[0]["brightness"], 51);
+    assert_eq!(status[0]["default"], true);
+}
+
+#[test_case(0, &["set", "128"] => 128)]
+#[test_case(0, &["set", "100%"] => 255)]
+#[test_case(200, &["set", "--increase", "50%"] => 200)]
+#[test_case(100, &["set", "--decrease", "50%"] => 100)]
+#[test_case(100, &["inc", "10"] => 110)]
+#[test_case(200, &["dec", "10%"] => 174)]
+#[test_case(250, &["inc", "10%"] => 255 ; "clamped to max")]
+#[test_case(10, &["dec", "20"] => 0 ; "clamped to zero")]
+#[test_case(0, &["set", "--duration", "100ms", "255"] => 255 ; "ramp")]
+fn test_write_brightness(initial: u32, args: &[&str]) -> u32 {
+    let sysfs = FakeSysfs::new();
+    let device = sysfs.backlight("acpi_video0").with("brightness", initial);
+    sysfs.run(args);
+    device.brightness()
+}
+
+#[test]
+fn test_ramp_steps() {
+    let sysfs = FakeSysfs::new();
+    let device = sysfs.backlight("acpi_video0");
+    let args = [
+        "set",
+        "--duration",
+        "1s",
+        "--freq",
+        "4",
+        "--curve",
+        "linear",
+        "255",
+    ];
+    let values = sysfs.run_sampling(&args, &device, "brightness");
+    assert_eq!(values, ["0", "64", "128", "191", "255"]);
+}
+
+#[test]
+fn test_daemon_non_utf8_argument() {
+    let sysfs = FakeSysfs::new();
+    let device = sysfs.backlight("acpi_video0");
+    let root = sysfs.root().with_file_name(OsStr::from_bytes(b"sys\xff"));
+    std::os::unix::fs::symlink(sysfs.root(), &root).unwrap();
+    let _daemon = sysfs.daemon();
+    let output = sysfs
+        .daemon_command(&root)
+        .args(["set", "100"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(device.brightness(), 100);
+}
+
+#[test]
+fn test_config() {
+    let sysfs = FakeSysfs::new();
+    let screen = sysfs.backlight("acpi_video0");
+    let kbd = sysfs.led("tpacpi::kbd_backlight").with("max_brightness", 2);
+    sysfs.config(
+        r#"
+        device = "kbd"
+
+        [aliases]
+        kbd = "*::kbd_backlight"
+
+        [devices.acpi_video0]
+        min = "10%"
+        "#,
+    );
+    sysfs.run(&["set", "100%"]);
+    assert_eq!(kbd.brightness(), 2);
+    assert_eq!(screen.brightness(), 0);
+    sysfs.run(&["-D", "acpi_video0", "set", "0"]);
+    assert_eq!(screen.brightness(), 26);
+}
+
+#[test]
+fn test_save_restore() {
+    let sysfs = FakeSysfs::new();
+    let device = sysfs.backlight("acpi_video0").with("brightness", 142);
+    sysfs.run(&["save"]);
+    sysfs.run(&["set", "200"]);
+    sysfs.run(&["restore"]);
+    assert_eq!(device.brightness(), 142);
+}
+
+#[test]
+fn test_power() {
+    let sysfs = FakeSysfs::new();
+    let device = sysfs.backlight("acpi_video0");
+    assert_eq!(sysfs.run(&["power", "status"]), "on\n");
+    sysfs.run(&["power", "off"]);
+    assert_eq!(device.read("bl_power"), "4");
+    assert_eq!(sysfs.run(&["power", "status"]), "off\n");
+    sysfs.run(&["power", "toggle"]);
+    assert_eq!(device.read("bl_power"), "0");
+}
+
+#[test]
+fn test_trigger() {
+    let sysfs = FakeSysfs::new();
+    // the kernel adds these attributes when the timer trigger is set
+    let led = sysfs
+        .led("input3::capslock")
+        .with("delay_on", 500)
+        .with("delay_off", 500);
+    assert_eq!(
+        sysfs.run(&["trigger", "list"]),
+        "none (current)\ntimer\nheartbeat\n"
+    );
+    sysfs.run(&[
+        "trigger",
+        "set",
+        "timer",
+        "--delay-on",
+        "100",
+        "--delay-off",
+        "900",
+    ]);
+    assert_eq!(led.read("trigger"), "timer");
+    assert_eq!(led.read("delay_on"), "100");
+    assert_eq!(led.read("delay_off"), "900");
+}
+
+#[test]
+fn test_netdev_trigger() {
+    let sysfs = FakeSysfs::new();
+    // as already set up for the netdev trigger
+    let led = sysfs
+        .led("input3::capslock")
+        .with("trigger", "none [netdev]")
+        .with("device_name", "eth0")
+        .with("link", 1)
+        .with("rx", 0)
+        .with("tx", 0);
+    sysfs.run(&["trigger", "set", "netdev", "--tx", "on"]);
+    assert_eq!(led.read("device_name"), "eth0");
+    assert_eq!(led.read("link"), "1");
+    assert_eq!(led.read("rx"), "0");
+    assert_eq!(led.read("tx"), "1");
+
+    sysfs.run(&["trigger", "set", "netdev", "--link", "off"]);
+    assert_eq!(led.read("link"), "0");
+    assert_eq!(led.read("tx"), "1");
+}
+
+#[test]
+fn test_blink() {
+    let sysfs = FakeSysfs::new();
+    let led = sysfs
+        .led("input3::capslock")
+        .with("max_brightness", 9)
+        .with("brightness", 7);
+    sysfs.run(&["blink", "--period", "20ms", "--cycles", "2"]);
+    assert_eq!(led.brightness(), 7);
+}
+
+#[test_case(false, "pattern" ; "software")]
+#[test_case(true, "hw_pattern" ; "hardware")]
+fn test_pattern_trigger(hardware: bool, attribute: &str) {
+    let sysfs = FakeSysfs::new();
+    let mut led = sysfs
+        .led("input3::capslock")
+        .with("trigger", "[none] timer pattern");
+    if hardware {
+        led = led.with("hw_pattern", "");
+    }
+    sysfs.run(&["pattern", "-n", "2", "100%:500ms", "0:1s"]);
+    assert_eq!(led.read("trigger"), "pattern");
+    assert_eq!(led.read("repeat"), "2");
+    assert_eq!(led.read(attribute), "255 500 255 0 0 1000 0 0");
+}
+
+#[test]
+fn test_pattern_trigger_failed() {
+    let sysfs = FakeSysfs::new();
+    let led = sysfs
+        .led("input3::capslock")
+        .with("trigger", "[timer] pattern");
+    // writing a directory fails
+    std::fs::create_dir(led.path().join("pattern")).unwrap();
+    sysfs.run_err(&["pattern", "100%:500ms"]);
+    assert_eq!(led.read("trigger"), "timer");
+}
+
+#[test_case(&["-D", "nope", "get"], "no devices match 'nope'")]
+#[test_case(&["-D", "[", "get"], "invalid device pattern '[': ")]
+#[test_case(&["-D", "input3::*", "power", "off"], "'input3::capslock' is not a backlight device")]
+#[test_case(&["-D", "acpi_video0", "trigger", "list"], "'acpi_video0' is not an LED device")]
+#[test_case(&["-D", "input3::*", "trigger", "set", "disco"], "unknown trigger 'disco' for 'input3::capslock'")]
+#[test_case(&["restore"], "no brightness has been saved for 'acpi_video0'")]
+fn test_error(args: &[&str], expected: &str) {
+    let sysfs = FakeSysfs::new();
+    sysfs.backlight("acpi_video0");
+    sysfs.led("input3::capslock");
+    let stderr = sysfs.run_err(args);
+    assert!(
+        stderr.starts_with(&format!("Error: {expected}")),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn test_no_devices() {
+    let sysfs = FakeSysfs::new();
+    assert_eq!(sysfs.run(&["list"]), "");
+    assert!(sysfs
+        .run_err(&["get"])
+        .starts_with("Error: failed to find a default device"));
+}
@@ -0,0 +1,212 @@
+//! A fake sysfs of regular files, for running the library and the `slight`
+//! command against backlight and LED devices without touching real hardware.
+//!
+//! Unlike sysfs attributes, regular files keep what is past the end of
+//! a write, so a test must not have the command replace a value with one
+//! of fewer digits, like `100` with `75`, which would read back as `750`.
+
+// each test crate uses only part of the fixture
+#![allow(dead_code)]
+
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use std::time::Duration;
+
+use slight::discovery::{FoundDevices, Sysfs};
+use tempfile::TempDir;
+
+/// A temporary directory laid out like `/sys`, which is removed when dropped.
+///
+/// The runtime, configuration and state directories of the command are
+/// kept in the same directory, so that tests never share locks or files.
+pub struct FakeSysfs {
+    dir: TempDir,
+}
+
+/// The directory of a device in a [`FakeSysfs`].
+#[derive(Clone)]
+pub struct FakeDevice {
+    path: PathBuf,
+}
+
+impl FakeSysfs {
+    pub fn new() -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["sys", "run", "config", "state"] {
+            std::fs::create_dir(dir.path().join(sub)).unwrap();
+        }
+        Self { dir }
+    }
+
+    pub fn root(&self) -> PathBuf {
+        self.dir.path().join("sys")
+    }
+
+    pub fn sysfs(&self) -> Sysfs {
+        Sysfs::new(self.root())
+    }
+
+    pub fn found_devices(&self) -> FoundDevices {
+        FoundDevices::new(self.sysfs())
+    }
+
+    /// Add a backlight, which is off, has a maximum of 255, and is of the `raw` type.
+    pub fn backlight(&self, name: &str) -> FakeDevice {
+        FakeDevice::create(self.sysfs().join("class/backlight").join(name))
+            .with("brightness", 0)
+            .with("actual_brightness", 0)
+            .with("max_brightness", 255)
+            .with("bl_power", 0)
+            .with("type", "raw")
+    }
+
+    /// Add an LED, which is off, has a maximum of 255, and has no trigger.
+    pub fn led(&self, name: &str) -> FakeDevice {
+        FakeDevice::create(self.sysfs().join("class/leds").join(name))
+            .with("brightness", 0)
+            .with("max_brightness", 255)
+            .with("trigger", "[none] timer heartbeat")
+    }
+
+    /// Write the configuration file of the command.
+    pub fn config(&self, contents: &str) {
+        let path = self.dir.path().join("config/slight/config.toml");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    /// The `slight` command, using the directories of this sysfs, without arguments.
+    fn bare_command(&self) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_slight"));
+        command
+            .env_remove("SLIGHT_SYSFS_ROOT")
+            .env("XDG_RUNTIME_DIR", self.dir.path().join("run"))
+            .env("XDG_CONFIG_HOME", self.dir.path().join("config"))
+            .env("XDG_STATE_HOME", self.dir.path().join("state"));
+        command
+    }
+
+    /// The `slight` command, using this sysfs and never the daemon.
+    pub fn command(&self) -> Command {
+        let mut command = self.bare_command();
+        command
+            .arg("--sysfs-root")
+            .arg(self.root())
+            .arg("--no-daemon");
+        command
+    }
+
+    /// The `slight` command, using the sysfs at `root` and the daemon.
+    pub fn daemon_command(&self, root: &Path) -> Command {
+        let mut command = self.bare_command();
+        command.arg("--sysfs-root").arg(root);
+        command
+    }
+
+    /// Start the daemon, and wait until it listens.
+    pub fn daemon(&self) -> Daemon {
+        let child = self
+            .bare_command()
+            .arg("daemon")
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let socket = self.dir.path().join("run/slight/slight.sock");
+        for _ in 0..500 {
+            if socket.exists() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(socket.exists(), "the daemon did not start");
+        Daemon(child)
+    }
+
+    /// Run the command with `args`, and return its standard output, or panic
+    /// with its standard error if it failed.
+    pub fn run(&self, args: &[&str]) -> String {
+        let output = self.command().args(args).output().unwrap();
+        assert!(
+            output.status.success(),
+            "`slight {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Run the command with `args`, and return every value that `attribute` of
+    /// `device` took in turn, by reading it until the command has finished.
+    ///
+    /// Values that last less than a few milliseconds may be missed,
+    /// so the command should write them well apart.
+    pub fn run_sampling(&self, args: &[&str], device: &FakeDevice, attribute: &str) -> Vec<String> {
+        let mut child = self.command().args(args).spawn().unwrap();
+        let mut values = vec![device.read(attribute)];
+        loop {
+            let exited = child.try_wait().unwrap();
+            let value = device.read(attribute);
+            if values.last() != Some(&value) {
+                values.push(value);
+            }
+            match exited {
+                Some(status) => {
+                    assert!(status.success(), "`slight {}` failed", args.join(" "));
+                    return values;
+                }
+                None => std::thread::sleep(Duration::from_millis(2)),
+            }
+        }
+    }
+
+    /// Run the command with `args`, and return its standard error, or panic if it succeeded.
+    pub fn run_err(&self, args: &[&str]) -> String {
+        let Output { status, stderr, .. } = self.command().args(args).output().unwrap();
+        assert!(!status.success(), "`slight {}` succeeded", args.join(" "));
+        String::from_utf8(stderr).unwrap()
+    }
+}
+
+/// A running daemon, which is killed when dropped.
+pub struct Daemon(Child);
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        _ = self.0.kill();
+        _ = self.0.wait();
+    }
+}
+
+impl FakeDevice {
+    fn create(path: PathBuf) -> Self {
+        std::fs::create_dir_all(&path).unwrap();
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Set an attribute, creating it if it does not exist.
+    pub fn with(self, attribute: &str, value: impl Display) -> Self {
+        self.write(attribute, value);
+        self
+    }
+
+    pub fn write(&self, attribute: &str, value: impl Display) {
+        std::fs::write(self.path.join(attribute), format!("{value}\n")).unwrap();
+    }
+
+    /// The value of an attribute, without surrounding whitespace.
+    pub fn read(&self, attribute: &str) -> String {
+        std::fs::read_to_string(self.path.join(attribute))
+            .unwrap()
+            .trim()
+            .to_owned()
+    }
+
+    pub fn brightness(&self) -> u32 {
+        self.read("brightness").parse().unwrap()
+    }
+}
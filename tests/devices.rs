@@ -0,0 +1,185 @@
+//! Runs the library against a fake sysfs, checking how devices are
+//! discovered and what sequence of values a ramp writes to them.
+
+mod common;
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use common::FakeSysfs;
+use slight::clock::{Clock, VirtualClock};
+use slight::curve::Curve;
+use slight::device::{Brightness, ReadNumResult, WriteResult};
+use slight::discovery::{default_device, find_devices, select_devices, Capability};
+use slight::easing::Easing;
+use slight::ramp::{
+    ramp_brightness, set_brightness_all, CancelToken, InterpolationOptions, RampTracker, Transition,
+};
+use slight::{open_device, BoxedDevice};
+use test_case::test_case;
+
+/// A device in a fake sysfs, which records every value written to it.
+struct RecordingDevice {
+    device: BoxedDevice,
+    written: Mutex<Vec<u32>>,
+}
+
+impl RecordingDevice {
+    fn open(sysfs: &FakeSysfs, name: &str) -> Self {
+        let detail = sysfs
+            .found_devices()
+            .iter()
+            .find(|detail| detail.name == name)
+            .cloned()
+            .unwrap();
+        Self {
+            device: open_device(&detail, false).unwrap(),
+            written: Mutex::default(),
+        }
+    }
+
+    fn written(&self) -> Vec<u32> {
+        self.written.lock().unwrap().clone()
+    }
+}
+
+impl Brightness for RecordingDevice {
+    fn brightness(&self) -> ReadNumResult<u32> {
+        self.device.brightness()
+    }
+
+    fn set_brightness(&self, value: u32) -> WriteResult {
+        self.device.set_brightness(value)?;
+        self.written.lock().unwrap().push(value);
+        Ok(())
+    }
+
+    fn max_brightness(&self) -> ReadNumResult<u32> {
+        self.device.max_brightness()
+    }
+}
+
+fn interpolate(frequency: u32, easing: Easing) -> InterpolationOptions {
+    InterpolationOptions {
+        frequency: Some(frequency),
+        easing,
+        ..InterpolationOptions::default()
+    }
+}
+
+#[test]
+fn test_find_devices() {
+    let sysfs = FakeSysfs::new();
+    sysfs.backlight("intel_backlight");
+    sysfs.backlight("acpi_video0").with("type", "firmware");
+    sysfs.led("input3::capslock");
+    sysfs
+        .led("multicolor:status")
+        .with("multi_index", "red green blue")
+        .with("multi_intensity", "0 0 0");
+    // missing `max_brightness`
+    std::fs::create_dir_all(sysfs.sysfs().join("class/leds/broken")).unwrap();
+    std::fs::write(sysfs.sysfs().join("class/leds/broken/brightness"), "0").unwrap();
+
+    let mut found = find_devices(&sysfs.sysfs())
+        .into_iter()
+        .map(|detail| (detail.name, detail.capability))
+        .collect::<Vec<_>>();
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    let found = found
+        .iter()
+        .map(|(name, capability)| format!("{name}: {capability}"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        [
+            "acpi_video0: Backlight",
+            "input3::capslock: Brightness",
+            "intel_backlight: Backlight",
+            "multicolor:status: Multicolor",
+        ]
+    );
+    let default = default_device(&sysfs.found_devices()).unwrap();
+    assert_eq!(default.name, "acpi_video0");
+}
+
+#[test_case(&["input3::*"] => vec!["input3::capslock", "input3::numlock"])]
+#[test_case(&["/sys/class/backlight/*"] => vec!["acpi_video0"])]
+#[test_case(&["acpi_video0", "input3::numlock"] => vec!["acpi_video0", "input3::numlock"])]
+fn test_select_devices(patterns: &[&str]) -> Vec<String> {
+    let sysfs = FakeSysfs::new();
+    sysfs.backlight("acpi_video0");
+    sysfs.led("input3::capslock");
+    sysfs.led("input3::numlock");
+    let patterns = patterns.iter().map(|&p| p.to_owned()).collect::<Vec<_>>();
+    let mut selected = select_devices(&patterns, &Default::default(), &sysfs.found_devices())
+        .unwrap()
+        .into_iter()
+        .map(|detail| {
+            assert!(detail.path.starts_with(sysfs.root()));
+            assert!(!matches!(detail.capability, Capability::None));
+            detail.name
+        })
+        .collect::<Vec<_>>();
+    selected.sort();
+    selected
+}
+
+#[test_case(0, 100, Easing::Linear => vec![20, 40, 60, 80, 100])]
+#[test_case(100, 0, Easing::Linear => vec![80, 60, 40, 20, 0])]
+#[test_case(0, 100, Easing::EaseIn => vec![4, 16, 36, 64, 100])]
+#[test_case(0, 3, Easing::Linear => vec![1, 2, 3] ; "fewer values than steps")]
+fn test_ramp_steps(start: u32, target: u32, easing: Easing) -> Vec<u32> {
+    let sysfs = FakeSysfs::new();
+    sysfs
+        .backlight("acpi_video0")
+        .with("brightness", start)
+        .with("max_brightness", 100);
+    let device = RecordingDevice::open(&sysfs, "acpi_video0");
+    let duration = Duration::from_secs(5);
+    let interpolate = interpolate(1, easing);
+    let (clock, cancel) = (VirtualClock::default(), CancelToken::default());
+    let start = clock.now();
+    ramp_brightness(
+        &device,
+        target,
+        duration,
+        &interpolate,
+        &Curve::Linear,
+        &clock,
+        &cancel,
+    )
+    .unwrap();
+    assert_eq!(clock.now() - start, duration);
+    device.written()
+}
+
+#[test]
+fn test_set_brightness_all() {
+    let sysfs = FakeSysfs::new();
+    let screen = sysfs.backlight("acpi_video0");
+    let kbd = sysfs.led("tpacpi::kbd_backlight").with("max_brightness", 2);
+    let transitions = sysfs
+        .found_devices()
+        .iter()
+        .map(|detail| {
+            let device = open_device(detail, false).unwrap();
+            let target = device.max_brightness().unwrap();
+            Transition {
+                path: detail.path.clone(),
+                device,
+                target,
+                curve: Curve::Linear,
+                portion: 1.0,
+            }
+        })
+        .collect();
+    let interpolate = InterpolationOptions {
+        duration: Some("10m".parse().unwrap()),
+        ..interpolate(30, Easing::Linear)
+    };
+    let (clock, tracker) = (VirtualClock::default(), RampTracker::default());
+    set_brightness_all(transitions, &interpolate, &tracker, &clock).unwrap();
+    assert_eq!(screen.brightness(), 255);
+    assert_eq!(kbd.brightness(), 2);
+}